@@ -0,0 +1,297 @@
+//! Async versions of the backend calls and streams of the changes on the sound server, on top of
+//! tokio. Nothing here blocks or needs a thread of its own, so a daemon, a D-Bus service or a GUI
+//! can run many operations at once.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use audio_tools::asynchronous;
+//! use futures_util::StreamExt;
+//!
+//! # async fn example() -> Result<(), audio_tools::Error> {
+//! asynchronous::fade(20, Duration::from_secs(2)).await?;
+//!
+//! let volumes = asynchronous::volumes();
+//! futures_util::pin_mut!(volumes);
+//! while let Some(volume) = volumes.next().await {
+//!     println!("{}%", volume?.volume);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::future::Future;
+use std::io;
+use std::process::Stdio;
+use std::time::Duration;
+
+use futures_util::future;
+use futures_util::stream::{self, Stream, StreamExt};
+use log::{debug, error, info};
+use tokio::io::{AsyncBufReadExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdout, Command};
+use tokio::time;
+
+use crate::config::{get_config_file_path, Config};
+use crate::error::Error;
+use crate::fade::{FadeToken, Ramp, STEP_INTERVAL};
+use crate::volume::{
+    cap_channel_volumes, cap_volume, cap_volume_change, VolumeInfo, GET_VOLUME_ARGS,
+};
+use crate::volume_control::{
+    check_output, parse_sink_inputs, parse_sinks, parse_sources, spawn_error, Event, Facility,
+    Sink, SinkInput, Source,
+};
+
+const DEFAULT_SINK: &str = "@DEFAULT_SINK@";
+
+async fn run_backend(program: &str, args: &[&str]) -> Result<String, Error> {
+    let output = Command::new(program).args(args).output().await;
+    check_output(program, args, output)
+}
+
+async fn run_pactl(args: &[&str]) -> Result<(), Error> {
+    run_backend("pactl", args).await?;
+    Ok(())
+}
+
+/// Loads the config file like `Config::load`, without blocking.
+async fn load_config() -> Config {
+    let content = match get_config_file_path() {
+        Ok(path) => tokio::fs::read_to_string(path).await.map_err(Error::from),
+        Err(e) => Err(e),
+    };
+    let config = match content {
+        Ok(content) => Config::parse(&content),
+        Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(e) => Err(e),
+    };
+    config.unwrap_or_else(|e| {
+        error!("Failed to load config, using the default one: {}", e);
+        Config::default()
+    })
+}
+
+/// Cap of the volume profiles of the config active right now.
+async fn get_max_volume() -> Option<i32> {
+    load_config().await.volume.get_current_max_volume()
+}
+
+pub async fn list_sinks() -> Result<Vec<Sink>, Error> {
+    parse_sinks(&run_backend("pacmd", &["list-sinks"]).await?)
+}
+
+pub async fn list_sources() -> Result<Vec<Source>, Error> {
+    parse_sources(&run_backend("pacmd", &["list-sources"]).await?)
+}
+
+pub async fn list_sink_inputs() -> Result<Vec<SinkInput>, Error> {
+    parse_sink_inputs(&run_backend("pacmd", &["list-sink-inputs"]).await?)
+}
+
+/// The volume of the default sink.
+pub async fn get_volume() -> Result<VolumeInfo, Error> {
+    VolumeInfo::parse(&run_backend("amixer", &GET_VOLUME_ARGS).await?)
+}
+
+/// Raises (positive) or lowers (negative) the volume of the default sink, in percent, within the
+/// cap of the active volume profile.
+pub async fn change_volume(amount: i32) -> Result<(), Error> {
+    let max_volume = get_max_volume().await;
+    let amount = match max_volume {
+        Some(_) if amount > 0 => cap_volume_change(get_volume().await?.volume, amount, max_volume),
+        _ => amount,
+    };
+    let direction_sign = if amount >= 0 { '+' } else { '-' };
+    let change = format!("{}%{}", amount.abs(), direction_sign);
+    run_backend("amixer", &["-D", "pulse", "sset", "Master", &change]).await?;
+    Ok(())
+}
+
+/// Sets the volume of each channel of a sink, given by index or name, in percent, within the cap
+/// of the active volume profile.
+pub async fn set_sink_volumes(sink: &str, volumes: &[i32]) -> Result<(), Error> {
+    set_capped_sink_volumes(sink, volumes, get_max_volume().await).await
+}
+
+async fn set_capped_sink_volumes(
+    sink: &str,
+    volumes: &[i32],
+    max_volume: Option<i32>,
+) -> Result<(), Error> {
+    let volumes: Vec<String> = cap_channel_volumes(volumes, max_volume)
+        .iter()
+        .map(|v| format!("{}%", v))
+        .collect();
+    let mut args = vec!["set-sink-volume", sink];
+    args.extend(volumes.iter().map(|v| v.as_str()));
+    run_pactl(&args).await
+}
+
+pub async fn set_sink_mute(sink: &str, muted: bool) -> Result<(), Error> {
+    run_pactl(&["set-sink-mute", sink, if muted { "1" } else { "0" }]).await
+}
+
+/// Mutes or unmutes the default sink.
+pub async fn toggle_mute() -> Result<(), Error> {
+    run_pactl(&["set-sink-mute", DEFAULT_SINK, "toggle"]).await
+}
+
+pub async fn set_default_sink(sink: &str) -> Result<(), Error> {
+    run_pactl(&["set-default-sink", sink]).await
+}
+
+pub async fn move_sink_input(sink_input: i32, sink: &str) -> Result<(), Error> {
+    run_pactl(&["move-sink-input", &sink_input.to_string(), sink]).await
+}
+
+/// Gradually changes the volume of the default sink to the target, at most the cap of the active
+/// volume profile, keeping the balance. Returns false if a newer fade, in this or any other
+/// process, cancelled this one. Dropping the future stops the fade as well.
+pub async fn fade(target: i32, duration: Duration) -> Result<bool, Error> {
+    let max_volume = get_max_volume().await;
+    let target = cap_volume(target, max_volume);
+    let token = FadeToken::acquire_async().await?;
+    let volume = get_volume().await?;
+    info!("Fading from {}% to {}%.", volume.volume, target);
+    let ramp = Ramp::to_target(&volume, target, duration);
+    let mut interval = time::interval(STEP_INTERVAL);
+    // The first tick is right away.
+    interval.tick().await;
+    let mut last_volumes = Vec::new();
+    for step in 1..=ramp.num_steps {
+        interval.tick().await;
+        if !token.is_current_async().await {
+            info!("Fade was cancelled by a newer one.");
+            return Ok(false);
+        }
+        let volumes = ramp.volumes_at(step);
+        if volumes != last_volumes {
+            set_capped_sink_volumes(DEFAULT_SINK, &volumes, max_volume).await?;
+            last_volumes = volumes;
+        }
+    }
+    Ok(true)
+}
+
+struct Subscription {
+    child: Child,
+    lines: Lines<BufReader<ChildStdout>>,
+}
+
+enum State {
+    Starting,
+    Running(Box<Subscription>),
+    Ended,
+}
+
+fn subscribe() -> Result<Box<Subscription>, Error> {
+    let mut child = Command::new("pactl")
+        .arg("subscribe")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| spawn_error("pactl", e))?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| Error::Unavailable(String::from("pactl subscribe has no output.")))?;
+    Ok(Box::new(Subscription {
+        child,
+        lines: BufReader::new(stdout).lines(),
+    }))
+}
+
+/// Why `pactl subscribe` stopped, once its output ended.
+async fn subscription_error(child: Child) -> Error {
+    let output = child.wait_with_output().await;
+    match check_output("pactl", &["subscribe"], output) {
+        Ok(_) => Error::Unavailable(String::from("pactl subscribe ended.")),
+        Err(e) => e,
+    }
+}
+
+/// The changes on the sound server, for as long as the stream is kept. Ends after an error, e.g.
+/// when the sound server stops.
+pub fn events() -> impl Stream<Item = Result<Event, Error>> {
+    stream::unfold(State::Starting, |state| async move {
+        let mut subscription = match state {
+            State::Starting => match subscribe() {
+                Ok(subscription) => subscription,
+                Err(e) => return Some((Err(e), State::Ended)),
+            },
+            State::Running(subscription) => subscription,
+            State::Ended => return None,
+        };
+        loop {
+            match subscription.lines.next_line().await {
+                Ok(Some(line)) => match Event::parse(&line) {
+                    Some(event) => return Some((Ok(event), State::Running(subscription))),
+                    None => debug!("Ignoring \"{}\" from pactl subscribe.", line),
+                },
+                Ok(None) => {
+                    let error = subscription_error(subscription.child).await;
+                    return Some((Err(error), State::Ended));
+                }
+                Err(e) => return Some((Err(e.into()), State::Ended)),
+            }
+        }
+    })
+}
+
+/// Queries something right away and again after each batch of events on the given facilities,
+/// skipping results that did not change.
+fn refreshed<T, F, Fut>(
+    facilities: &'static [Facility],
+    query: F,
+) -> impl Stream<Item = Result<T, Error>>
+where
+    T: Clone + PartialEq,
+    F: Fn() -> Fut + Copy,
+    Fut: Future<Output = Result<T, Error>>,
+{
+    // A single change, e.g. of the volume, often comes as several events.
+    const MAX_BATCH: usize = 64;
+    let changes = events()
+        .filter(move |event| {
+            future::ready(match event {
+                Ok(event) => facilities.contains(&event.facility),
+                Err(_) => true,
+            })
+        })
+        .ready_chunks(MAX_BATCH)
+        .map(|batch| batch.into_iter().try_for_each(|event| event.map(|_| ())));
+    let mut last: Option<T> = None;
+    stream::once(future::ready(Ok(())))
+        .chain(changes)
+        .then(move |trigger| async move {
+            trigger?;
+            query().await
+        })
+        .filter_map(move |result| {
+            future::ready(match result {
+                Ok(value) if last.as_ref() == Some(&value) => None,
+                Ok(value) => {
+                    last = Some(value.clone());
+                    Some(Ok(value))
+                }
+                Err(e) => Some(Err(e)),
+            })
+        })
+}
+
+/// The sinks, right away and again whenever they changed.
+pub fn sinks() -> impl Stream<Item = Result<Vec<Sink>, Error>> {
+    refreshed(&[Facility::Sink, Facility::Server], list_sinks)
+}
+
+/// The streams playing on the sinks, right away and again whenever they changed.
+pub fn sink_inputs() -> impl Stream<Item = Result<Vec<SinkInput>, Error>> {
+    refreshed(&[Facility::SinkInput], list_sink_inputs)
+}
+
+/// The volume of the default sink, right away and again whenever it changed.
+pub fn volumes() -> impl Stream<Item = Result<VolumeInfo, Error>> {
+    refreshed(&[Facility::Sink, Facility::Server], get_volume)
+}
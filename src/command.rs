@@ -0,0 +1,673 @@
+use std::time::Duration;
+
+use chrono::Local;
+use log::info;
+
+use crate::config::Config;
+use crate::error::Error;
+use crate::interface::Interface;
+use crate::mpris::PlayerAction;
+use crate::picker::Chooser;
+use crate::schedule::{self, next_time_of_day, parse_delay};
+use crate::state::{load_data_from_file, write_data_to_file, ARG_SEPARATOR};
+use crate::volume::{BalanceDirection, MAX_VOLUME};
+
+/// What `select_input` switches.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SelectTarget {
+    Sink,
+    Source,
+    /// Port of the active sink, e.g. speakers or headphones.
+    Port,
+    /// Profile of a card, e.g. analog stereo or HDMI.
+    Profile,
+    /// Sink a single stream plays on.
+    Stream,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CommandType {
+    NextInput,
+    VolumeLower,
+    VolumeRaise,
+    VolumeToggleMute,
+    VolumeNotification,
+    MicrophoneToggleMute,
+    Player(PlayerAction),
+    Meter {
+        source: Option<String>,
+        duration: Duration,
+    },
+    Balance {
+        direction: BalanceDirection,
+        amount: i32,
+    },
+    Fade {
+        target: i32,
+        duration: Duration,
+    },
+    VolumeToggleMuteRamped {
+        duration: Duration,
+    },
+    Schedule {
+        time: i64,
+        args: Vec<String>,
+    },
+    ScheduleList,
+    ScheduleClear,
+    PushToTalkStart,
+    PushToTalkStop,
+    SceneSave {
+        name: String,
+    },
+    SceneLoad {
+        name: String,
+    },
+    Mixer,
+    Select {
+        target: SelectTarget,
+        picker: Option<String>,
+        name: Option<String>,
+    },
+    Combine {
+        name: String,
+        interfaces: Vec<String>,
+    },
+    Uncombine {
+        name: String,
+    },
+    Daemon,
+}
+
+impl CommandType {
+    /// Whether the command may come from the network or MQTT. Those only change the volume, mute
+    /// or switch between interfaces, nothing that runs other programs, writes files or outlives
+    /// the command.
+    pub(crate) fn is_remote(&self) -> bool {
+        match self {
+            CommandType::NextInput
+            | CommandType::VolumeLower
+            | CommandType::VolumeRaise
+            | CommandType::VolumeToggleMute
+            | CommandType::VolumeNotification
+            | CommandType::MicrophoneToggleMute
+            | CommandType::Player(_)
+            | CommandType::Balance { .. }
+            | CommandType::Fade { .. }
+            | CommandType::VolumeToggleMuteRamped { .. } => true,
+            CommandType::Select { picker, .. } => picker.is_none(),
+            _ => false,
+        }
+    }
+
+    /// Why the command cannot run inside the daemon, e.g. from a hotkey, the tray, the socket or
+    /// the schedule. Push-to-talk would take over the signal handlers of the daemon and record
+    /// it as the process holding the microphone.
+    pub(crate) fn daemon_error(&self) -> Option<&'static str> {
+        match self {
+            CommandType::Daemon => Some("The daemon is already running."),
+            CommandType::Mixer => Some("The mixer needs a terminal."),
+            CommandType::PushToTalkStart | CommandType::PushToTalkStop => {
+                Some("Push-to-talk needs a process of its own, run it from the command line.")
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Value following an option like "--duration".
+fn get_option<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    args.iter()
+        .position(|a| a == name)
+        .and_then(|i| args.get(i + 1))
+}
+
+fn get_duration_option(args: &[String], name: &str) -> Result<Option<Duration>, Error> {
+    match get_option(args, name) {
+        Some(milliseconds) => Ok(Some(Duration::from_millis(
+            milliseconds
+                .parse()
+                .map_err(|e| Error::parse(milliseconds, e))?,
+        ))),
+        None => Ok(None),
+    }
+}
+
+/// Parses a volume in percent like "30" or "30%", rounded and between 0 and the maximum.
+pub(crate) fn parse_volume(text: &str, max_volume: i32) -> Result<i32, Error> {
+    let volume = text
+        .trim_end_matches('%')
+        .parse::<f32>()
+        .map_err(|e| Error::parse(text, e))?
+        .round();
+    if !(0.0..=max_volume as f32).contains(&volume) {
+        return Err(Error::parse(
+            text,
+            format!("The volume has to be between 0% and {}%.", max_volume),
+        ));
+    }
+    Ok(volume as i32)
+}
+
+/// Name of a combined sink, "combined" by default. The sound server only takes simple names.
+fn parse_sink_name(name: Option<&str>) -> Result<String, Error> {
+    let name = name.unwrap_or("combined");
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+    {
+        return Err(Error::Usage(format!(
+            "Invalid sink name \"{}\", use letters, digits, '_', '-' and '.'.",
+            name
+        )));
+    }
+    Ok(name.to_string())
+}
+
+/// Parses a command and its arguments, without the program name.
+pub fn parse_command(args: &[String]) -> Result<CommandType, Error> {
+    if args.is_empty() {
+        return Err(Error::Usage(String::from("Not enough arguments!")));
+    }
+
+    let ret = match &args[0].as_str() {
+        &"next_input" => CommandType::NextInput,
+        &"lower" => CommandType::VolumeLower,
+        &"raise" => CommandType::VolumeRaise,
+        &"mute" => match get_duration_option(args, "--fade")? {
+            Some(duration) => CommandType::VolumeToggleMuteRamped { duration },
+            None => CommandType::VolumeToggleMute,
+        },
+        &"volume_notification" => CommandType::VolumeNotification,
+        &"mic_mute" => CommandType::MicrophoneToggleMute,
+        &"play-pause" => CommandType::Player(PlayerAction::PlayPause),
+        &"next" => CommandType::Player(PlayerAction::Next),
+        &"prev" => CommandType::Player(PlayerAction::Previous),
+        &"meter" => {
+            let source = args.get(1).filter(|s| s.parse::<u64>().is_err()).cloned();
+            let seconds = match args.get(if source.is_some() { 2 } else { 1 }) {
+                Some(seconds) => seconds
+                    .parse::<u64>()
+                    .map_err(|e| Error::parse(seconds, e))?,
+                None => 10,
+            };
+            CommandType::Meter {
+                source,
+                duration: Duration::from_secs(seconds),
+            }
+        }
+        &"balance" => {
+            let direction = match args.get(1).map(|s| s.as_str()) {
+                Some("left") => BalanceDirection::Left,
+                Some("right") => BalanceDirection::Right,
+                Some("center") => BalanceDirection::Center,
+                _ => {
+                    return Err(Error::Usage(String::from(
+                        "Usage: balance left|right|center [N]",
+                    )))
+                }
+            };
+            let amount = match args.get(2) {
+                Some(amount) => amount.parse::<i32>().map_err(|e| Error::parse(amount, e))?,
+                None => 10,
+            };
+            CommandType::Balance { direction, amount }
+        }
+        &"fade" => {
+            let target = match args.get(1) {
+                Some(target) => parse_volume(target, MAX_VOLUME)?,
+                None => {
+                    return Err(Error::Usage(String::from(
+                        "Usage: fade <target%> [--duration <ms>]",
+                    )))
+                }
+            };
+            let duration =
+                get_duration_option(args, "--duration")?.unwrap_or(Duration::from_secs(1));
+            CommandType::Fade { target, duration }
+        }
+        &"schedule" => {
+            const USAGE: &str =
+                "Usage: schedule in <delay>|at <HH:MM> <command>, schedule list|clear";
+            match args.get(1).map(|s| s.as_str()) {
+                Some("list") => CommandType::ScheduleList,
+                Some("clear") => CommandType::ScheduleClear,
+                Some(kind) if (kind == "in" || kind == "at") && args.len() > 3 => {
+                    let command = &args[3..];
+                    if command
+                        .iter()
+                        .any(|a| a.contains(ARG_SEPARATOR) || a.contains('\n'))
+                    {
+                        return Err(Error::Usage(String::from(
+                            "Scheduled commands cannot contain tabs or line breaks.",
+                        )));
+                    }
+                    // Catch mistakes now instead of when the command is due.
+                    if let Some(error) = parse_command(command)?.daemon_error() {
+                        return Err(Error::Usage(format!(
+                            "\"{}\" cannot be scheduled. {}",
+                            command.join(" "),
+                            error
+                        )));
+                    }
+                    let time = if kind == "in" {
+                        Local::now().timestamp() + parse_delay(&args[2])?.as_secs() as i64
+                    } else {
+                        next_time_of_day(&args[2])?
+                    };
+                    CommandType::Schedule {
+                        time,
+                        args: command.to_vec(),
+                    }
+                }
+                _ => return Err(Error::Usage(String::from(USAGE))),
+            }
+        }
+        &"ptt" => match args.get(1).map(|s| s.as_str()) {
+            Some("start") => CommandType::PushToTalkStart,
+            Some("stop") => CommandType::PushToTalkStop,
+            _ => return Err(Error::Usage(String::from("Usage: ptt start|stop"))),
+        },
+        &"scene" => match (args.get(1).map(|s| s.as_str()), args.get(2)) {
+            (Some("save"), Some(name)) => CommandType::SceneSave { name: name.clone() },
+            (Some("load"), Some(name)) => CommandType::SceneLoad { name: name.clone() },
+            _ => return Err(Error::Usage(String::from("Usage: scene save|load <name>"))),
+        },
+        &"tui" => CommandType::Mixer,
+        &"select_input" => {
+            const USAGE: &str =
+                "Usage: select_input [sink|source|port|profile|stream] --picker <cmd>|<name>";
+            let (target, rest) = match args.get(1).map(|s| s.as_str()) {
+                Some("sink") => (SelectTarget::Sink, &args[2..]),
+                Some("source") => (SelectTarget::Source, &args[2..]),
+                Some("port") => (SelectTarget::Port, &args[2..]),
+                Some("profile") => (SelectTarget::Profile, &args[2..]),
+                Some("stream") => (SelectTarget::Stream, &args[2..]),
+                _ => (SelectTarget::Sink, &args[1..]),
+            };
+            let picker = get_option(rest, "--picker").cloned();
+            let name = match rest.first() {
+                Some(name) if picker.is_none() => Some(name.clone()),
+                _ => None,
+            };
+            // A stream needs to be picked first, it has no name to give.
+            if (picker.is_none() && name.is_none())
+                || (target == SelectTarget::Stream && picker.is_none())
+            {
+                return Err(Error::Usage(String::from(USAGE)));
+            }
+            CommandType::Select {
+                target,
+                picker,
+                name,
+            }
+        }
+        &"combine" => {
+            let mut name = None;
+            let mut interfaces = Vec::new();
+            let mut rest = args[1..].iter();
+            while let Some(arg) = rest.next() {
+                if arg == "--name" {
+                    match rest.next() {
+                        Some(value) => name = Some(value.as_str()),
+                        None => {
+                            return Err(Error::Usage(String::from(
+                                "Usage: combine [--name <name>] [<interface>...]",
+                            )))
+                        }
+                    }
+                } else {
+                    interfaces.push(arg.clone());
+                }
+            }
+            CommandType::Combine {
+                name: parse_sink_name(name)?,
+                interfaces,
+            }
+        }
+        &"uncombine" => CommandType::Uncombine {
+            name: parse_sink_name(args.get(1).map(|s| s.as_str()))?,
+        },
+        &"daemon" => CommandType::Daemon,
+        command => {
+            return Err(Error::Usage(format!("Unknown command \"{}\"!", command)));
+        }
+    };
+    Ok(ret)
+}
+
+pub fn run_command(interface: &mut Interface, command: CommandType) -> Result<(), Error> {
+    match command {
+        CommandType::NextInput => {
+            info!("Received: CycleInputs");
+            interface.cycle_through_interfaces()
+        }
+        CommandType::VolumeLower => {
+            info!("Received: VolumeLower");
+            interface.step_volume(-1)
+        }
+        CommandType::VolumeRaise => {
+            info!("Received: VolumeRaise");
+            interface.step_volume(1)
+        }
+        CommandType::VolumeToggleMute => {
+            info!("Received: VolumeToggleMute");
+            interface.toggle_mute()
+        }
+        CommandType::VolumeNotification => {
+            info!("Received: ShowVolume");
+            interface.show_volume_notification(true)
+        }
+        CommandType::MicrophoneToggleMute => {
+            info!("Received: MicrophoneToggleMute");
+            interface.toggle_microphone_mute()
+        }
+        CommandType::Player(action) => {
+            info!("Received: Player {:?}", action);
+            interface.control_player(action)
+        }
+        CommandType::Meter { source, duration } => {
+            info!("Received: Meter");
+            interface.show_meter(source.as_deref(), duration)
+        }
+        CommandType::Balance { direction, amount } => {
+            info!("Received: Balance {:?} {}", direction, amount);
+            interface.change_balance(direction, amount)
+        }
+        CommandType::Fade { target, duration } => {
+            info!("Received: Fade {}% {:?}", target, duration);
+            interface.fade(target, duration)
+        }
+        CommandType::VolumeToggleMuteRamped { duration } => {
+            info!("Received: VolumeToggleMuteRamped {:?}", duration);
+            interface.toggle_mute_ramped(duration)
+        }
+        CommandType::Schedule { time, args } => {
+            info!("Received: Schedule {:?} at {}", args, time);
+            schedule::add(time, &args)
+        }
+        CommandType::ScheduleList => {
+            info!("Received: ScheduleList");
+            schedule::list();
+            Ok(())
+        }
+        CommandType::ScheduleClear => {
+            info!("Received: ScheduleClear");
+            schedule::clear()
+        }
+        CommandType::PushToTalkStart => {
+            info!("Received: PushToTalkStart");
+            interface.push_to_talk()
+        }
+        CommandType::PushToTalkStop => {
+            info!("Received: PushToTalkStop");
+            interface.stop_push_to_talk()
+        }
+        CommandType::SceneSave { name } => {
+            info!("Received: SceneSave {}", name);
+            interface.save_scene(&name)
+        }
+        CommandType::SceneLoad { name } => {
+            info!("Received: SceneLoad {}", name);
+            interface.load_scene(&name)
+        }
+        CommandType::Mixer => {
+            info!("Received: Mixer");
+            interface.run_mixer()
+        }
+        CommandType::Select {
+            target,
+            picker,
+            name,
+        } => {
+            info!("Received: Select {:?}", target);
+            let chooser = match (&picker, &name) {
+                (Some(picker), _) => Chooser::Picker(picker),
+                (None, Some(name)) => Chooser::Name(name),
+                (None, None) => return Err(Error::Usage(String::from("Nothing to select."))),
+            };
+            interface.select_input(target, &chooser)
+        }
+        CommandType::Combine { name, interfaces } => {
+            info!("Received: Combine {} {:?}", name, interfaces);
+            interface.combine_interfaces(&name, &interfaces)
+        }
+        CommandType::Uncombine { name } => {
+            info!("Received: Uncombine {}", name);
+            interface.uncombine_interfaces(&name)
+        }
+        CommandType::Daemon => Err(Error::Usage(String::from(
+            "The daemon cannot be started from here.",
+        ))),
+    }
+}
+
+/// Runs a command with the notification IDs of the previous run, so that its notifications
+/// replace the previous ones.
+pub fn execute_command(config: &Config, command: CommandType) -> Result<(), Error> {
+    let (volume_notification_id, sink_notification_id) =
+        load_data_from_file().unwrap_or((None, None));
+    let mut interface = Interface::new(config, volume_notification_id, sink_notification_id)?;
+    run_command(&mut interface, command)?;
+    let (volume_notification_id, sink_notification_id) = interface.get_notification_ids();
+    write_data_to_file(volume_notification_id, sink_notification_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(command: &str) -> Result<CommandType, Error> {
+        let args: Vec<String> = command.split_whitespace().map(String::from).collect();
+        parse_command(&args)
+    }
+
+    #[test]
+    fn parses_simple_commands() {
+        assert_eq!(parse("raise").unwrap(), CommandType::VolumeRaise);
+        assert_eq!(
+            parse("prev").unwrap(),
+            CommandType::Player(PlayerAction::Previous)
+        );
+        assert!(matches!(parse(""), Err(Error::Usage(_))));
+        assert!(matches!(parse("louder"), Err(Error::Usage(_))));
+    }
+
+    #[test]
+    fn parses_meter() {
+        assert_eq!(
+            parse("meter mic 3").unwrap(),
+            CommandType::Meter {
+                source: Some(String::from("mic")),
+                duration: Duration::from_secs(3)
+            }
+        );
+        assert_eq!(
+            parse("meter 5").unwrap(),
+            CommandType::Meter {
+                source: None,
+                duration: Duration::from_secs(5)
+            }
+        );
+        assert_eq!(
+            parse("meter").unwrap(),
+            CommandType::Meter {
+                source: None,
+                duration: Duration::from_secs(10)
+            }
+        );
+    }
+
+    #[test]
+    fn parses_balance() {
+        assert_eq!(
+            parse("balance left").unwrap(),
+            CommandType::Balance {
+                direction: BalanceDirection::Left,
+                amount: 10
+            }
+        );
+        assert_eq!(
+            parse("balance right 25").unwrap(),
+            CommandType::Balance {
+                direction: BalanceDirection::Right,
+                amount: 25
+            }
+        );
+        assert!(matches!(parse("balance up"), Err(Error::Usage(_))));
+        assert!(matches!(
+            parse("balance left much"),
+            Err(Error::ParseError { .. })
+        ));
+    }
+
+    #[test]
+    fn parses_fades() {
+        assert_eq!(
+            parse("fade 30% --duration 2000").unwrap(),
+            CommandType::Fade {
+                target: 30,
+                duration: Duration::from_secs(2)
+            }
+        );
+        assert_eq!(
+            parse("fade 30").unwrap(),
+            CommandType::Fade {
+                target: 30,
+                duration: Duration::from_secs(1)
+            }
+        );
+        assert_eq!(parse("mute").unwrap(), CommandType::VolumeToggleMute);
+        assert_eq!(
+            parse("mute --fade 300").unwrap(),
+            CommandType::VolumeToggleMuteRamped {
+                duration: Duration::from_millis(300)
+            }
+        );
+        assert!(matches!(parse("fade"), Err(Error::Usage(_))));
+        assert!(matches!(
+            parse("fade 30 --duration soon"),
+            Err(Error::ParseError { .. })
+        ));
+    }
+
+    #[test]
+    fn parses_schedule() {
+        assert!(matches!(
+            parse("schedule in 10m mute --fade 500").unwrap(),
+            CommandType::Schedule { args, .. } if args == ["mute", "--fade", "500"]
+        ));
+        let args: Vec<String> = ["schedule", "at", "23:00", "select_input", "Built-in Audio"]
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        assert!(matches!(
+            parse_command(&args).unwrap(),
+            CommandType::Schedule { args, .. } if args == ["select_input", "Built-in Audio"]
+        ));
+        let args: Vec<String> = ["schedule", "in", "5", "select_input", "a\nb"]
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        assert!(matches!(parse_command(&args), Err(Error::Usage(_))));
+        assert_eq!(parse("schedule list").unwrap(), CommandType::ScheduleList);
+        assert_eq!(parse("schedule clear").unwrap(), CommandType::ScheduleClear);
+        assert!(matches!(
+            parse("schedule in 10m daemon"),
+            Err(Error::Usage(_))
+        ));
+        assert!(matches!(
+            parse("schedule in 10m louder"),
+            Err(Error::Usage(_))
+        ));
+        assert!(matches!(parse("schedule in 10m"), Err(Error::Usage(_))));
+        assert!(matches!(
+            parse("schedule at 25:00 mute"),
+            Err(Error::ParseError { .. })
+        ));
+    }
+
+    #[test]
+    fn parses_select_input() {
+        assert_eq!(
+            parse("select_input HDMI").unwrap(),
+            CommandType::Select {
+                target: SelectTarget::Sink,
+                picker: None,
+                name: Some(String::from("HDMI")),
+            }
+        );
+        assert_eq!(
+            parse("select_input port --picker rofi").unwrap(),
+            CommandType::Select {
+                target: SelectTarget::Port,
+                picker: Some(String::from("rofi")),
+                name: None,
+            }
+        );
+        assert!(matches!(parse("select_input"), Err(Error::Usage(_))));
+        assert!(matches!(
+            parse("select_input stream Firefox"),
+            Err(Error::Usage(_))
+        ));
+    }
+
+    #[test]
+    fn allows_only_volume_commands_remotely() {
+        assert!(parse("raise").unwrap().is_remote());
+        assert!(parse("fade 20").unwrap().is_remote());
+        assert!(parse("select_input HDMI").unwrap().is_remote());
+        assert!(!parse("select_input --picker rofi").unwrap().is_remote());
+        assert!(!parse("schedule list").unwrap().is_remote());
+        assert!(!parse("scene load day").unwrap().is_remote());
+        assert!(!parse("tui").unwrap().is_remote());
+        assert!(!parse("daemon").unwrap().is_remote());
+    }
+
+    #[test]
+    fn parses_volumes_in_range() {
+        assert_eq!(parse_volume("30%", 100).unwrap(), 30);
+        assert_eq!(parse_volume("49.6", 100).unwrap(), 50);
+        assert_eq!(parse_volume("150", MAX_VOLUME).unwrap(), 150);
+        assert!(matches!(
+            parse_volume("101", 100),
+            Err(Error::ParseError { .. })
+        ));
+        assert!(matches!(
+            parse_volume("-1", 100),
+            Err(Error::ParseError { .. })
+        ));
+        assert!(matches!(
+            parse_volume("loud", 100),
+            Err(Error::ParseError { .. })
+        ));
+        assert!(matches!(parse("fade 500"), Err(Error::ParseError { .. })));
+    }
+
+    #[test]
+    fn parses_combine() {
+        assert_eq!(
+            parse("combine --name both HDMI Speakers").unwrap(),
+            CommandType::Combine {
+                name: String::from("both"),
+                interfaces: vec![String::from("HDMI"), String::from("Speakers")],
+            }
+        );
+        assert_eq!(
+            parse("combine").unwrap(),
+            CommandType::Combine {
+                name: String::from("combined"),
+                interfaces: Vec::new(),
+            }
+        );
+        assert_eq!(
+            parse("uncombine").unwrap(),
+            CommandType::Uncombine {
+                name: String::from("combined")
+            }
+        );
+        assert!(matches!(parse("combine --name"), Err(Error::Usage(_))));
+        assert!(matches!(parse("combine --name a;b"), Err(Error::Usage(_))));
+    }
+}
@@ -0,0 +1,429 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, NaiveTime};
+use log::{debug, error};
+use serde::Deserialize;
+
+use crate::error::Error;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum FeedbackKind {
+    #[default]
+    Notification,
+    Terminal,
+    Pipe,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum PipeFormat {
+    #[default]
+    Wob,
+    Xob,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct PipeConfig {
+    /// Write to the pipe in addition to the selected feedback.
+    pub enabled: bool,
+    pub path: PathBuf,
+    pub format: PipeFormat,
+    /// Appended to the value for wob when muted, either a style name or a set of colors.
+    pub muted_style: String,
+}
+
+impl Default for PipeConfig {
+    fn default() -> Self {
+        let runtime_dir = env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| String::from("/tmp"));
+        PipeConfig {
+            enabled: false,
+            path: Path::new(&runtime_dir).join("wob.sock"),
+            format: PipeFormat::Wob,
+            muted_style: String::new(),
+        }
+    }
+}
+
+/// Sounds for the different events. Each is a sound name from the theme, a path to a file or
+/// "off".
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct SoundEventsConfig {
+    pub volume_change: String,
+    pub mute: String,
+    pub unmute: String,
+    pub input_switched: String,
+    pub max_volume: String,
+}
+
+impl Default for SoundEventsConfig {
+    fn default() -> Self {
+        SoundEventsConfig {
+            volume_change: String::from("audio-volume-change"),
+            mute: String::from("device-removed"),
+            unmute: String::from("device-added"),
+            input_switched: String::from("message"),
+            max_volume: String::from("dialog-warning"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct SoundConfig {
+    pub theme: String,
+    /// Play a synthesized tone instead of the theme sounds.
+    pub tone: bool,
+    pub events: SoundEventsConfig,
+    /// Play the sound if all streams on the running interface are corked (paused).
+    pub check_corked: bool,
+    /// Play the sound if the monitor of the running interface is silent.
+    pub check_peak: bool,
+    /// Peak level between 0 and 1 below which the interface counts as silent.
+    pub peak_threshold: f32,
+}
+
+impl Default for SoundConfig {
+    fn default() -> Self {
+        SoundConfig {
+            theme: String::from("freedesktop"),
+            tone: false,
+            events: SoundEventsConfig::default(),
+            check_corked: true,
+            check_peak: false,
+            peak_threshold: 0.01,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum StepMode {
+    /// Fixed steps on the volume scale of the sound server.
+    #[default]
+    Linear,
+    /// Steps in decibels.
+    Db,
+    /// Steps on a cubic curve, fine at low and coarse at high volumes.
+    Cubic,
+}
+
+/// Caps the volume between two times of the day, e.g. "22:00" to "07:00".
+#[derive(Clone, Debug, Deserialize)]
+#[non_exhaustive]
+pub struct VolumeProfile {
+    pub start: String,
+    pub end: String,
+    pub max_volume: i32,
+}
+
+impl VolumeProfile {
+    fn is_active(&self, time: NaiveTime) -> bool {
+        let parse = |t: &str| NaiveTime::parse_from_str(t, "%H:%M");
+        match (parse(&self.start), parse(&self.end)) {
+            (Ok(start), Ok(end)) if start <= end => start <= time && time < end,
+            // Spans midnight.
+            (Ok(start), Ok(end)) => time >= start || time < end,
+            _ => {
+                error!(
+                    "Invalid times in volume profile {} - {}.",
+                    self.start, self.end
+                );
+                false
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct VolumeConfig {
+    pub step_mode: StepMode,
+    /// Size of a step in percent or dB, depending on the mode.
+    pub step: Option<f32>,
+    /// Show the volume in dB in the notification title.
+    pub show_db: bool,
+    pub profiles: Vec<VolumeProfile>,
+}
+
+impl VolumeConfig {
+    /// Lowest cap of the profiles active at the given time.
+    pub fn get_max_volume(&self, time: NaiveTime) -> Option<i32> {
+        self.profiles
+            .iter()
+            .filter(|p| p.is_active(time))
+            .map(|p| p.max_volume)
+            .min()
+    }
+
+    /// Cap of the profiles active right now.
+    pub fn get_current_max_volume(&self) -> Option<i32> {
+        self.get_max_volume(Local::now().time())
+    }
+
+    pub fn get_step(&self) -> f32 {
+        self.step.unwrap_or(match self.step_mode {
+            StepMode::Linear | StepMode::Cubic => 5.0,
+            StepMode::Db => 3.0,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct SpeechConfig {
+    /// Speak the name of the new output after switching.
+    pub enabled: bool,
+    /// Command writing WAV to stdout, which gets the text on stdin. An argument that is exactly
+    /// "{text}" is replaced by the text. It is run directly, never through a shell.
+    pub command: Vec<String>,
+}
+
+impl Default for SpeechConfig {
+    fn default() -> Self {
+        SpeechConfig {
+            enabled: false,
+            command: vec![
+                String::from("espeak-ng"),
+                String::from("--stdout"),
+                String::from("--stdin"),
+            ],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct MprisConfig {
+    /// Pause the playing media players when muting and resume them when unmuting.
+    pub pause_on_mute: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum DuckingMode {
+    /// Lower the other streams.
+    #[default]
+    Duck,
+    /// Pause the players of the other streams, or mute the streams without a player.
+    Cork,
+}
+
+/// Lowers or pauses the other streams during calls.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct DuckingConfig {
+    pub enabled: bool,
+    pub mode: DuckingMode,
+    /// How much the other streams are lowered, in dB.
+    pub db: f32,
+    /// Streams with these media roles are calls.
+    pub roles: Vec<String>,
+    /// Streams of these applications are calls, whatever their role.
+    pub applications: Vec<String>,
+}
+
+impl Default for DuckingConfig {
+    fn default() -> Self {
+        DuckingConfig {
+            enabled: false,
+            mode: DuckingMode::Duck,
+            db: 12.0,
+            roles: vec![String::from("phone")],
+            applications: Vec::new(),
+        }
+    }
+}
+
+/// Icon in the tray of the desktop.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct TrayConfig {
+    /// Let the daemon show the icon.
+    pub enabled: bool,
+}
+
+/// HTTP listener for phones and other computers.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct NetworkConfig {
+    /// Let the daemon listen.
+    pub enabled: bool,
+    /// Address and port to listen on.
+    pub address: String,
+    /// Clients send it as `Authorization: Bearer <token>`. Required.
+    pub token: String,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            enabled: false,
+            address: String::from("127.0.0.1:8421"),
+            token: String::new(),
+        }
+    }
+}
+
+/// Publishes the state to an MQTT broker and takes commands from it.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct MqttConfig {
+    /// Let the daemon connect to the broker.
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    /// No authentication if empty.
+    pub username: String,
+    pub password: String,
+    pub client_id: String,
+    /// Prefix of the state and command topics, e.g. "audio_tools/volume" and "audio_tools/set".
+    pub topic: String,
+    /// Publish Home Assistant discovery payloads.
+    pub discovery: bool,
+    pub discovery_prefix: String,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        MqttConfig {
+            enabled: false,
+            host: String::from("localhost"),
+            port: 1883,
+            username: String::new(),
+            password: String::new(),
+            client_id: String::from("audio_tools"),
+            topic: String::from("audio_tools"),
+            discovery: true,
+            discovery_prefix: String::from("homeassistant"),
+        }
+    }
+}
+
+/// Runs a command when a key is pressed, while the modifiers are held.
+#[derive(Clone, Debug, Deserialize)]
+#[non_exhaustive]
+pub struct HotkeyBinding {
+    /// Linux key name, e.g. "KEY_F9".
+    pub key: String,
+    #[serde(default)]
+    pub modifiers: Vec<String>,
+    pub command: String,
+    /// Run the command again while the key is held.
+    #[serde(default)]
+    pub repeat: bool,
+}
+
+impl HotkeyBinding {
+    fn new(key: &str, command: &str, repeat: bool) -> Self {
+        HotkeyBinding {
+            key: key.to_string(),
+            modifiers: Vec::new(),
+            command: command.to_string(),
+            repeat,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct HotkeysConfig {
+    /// Let the daemon read the keys from the input devices.
+    pub enabled: bool,
+    /// Bind the volume and mute keys.
+    pub media_keys: bool,
+    pub bindings: Vec<HotkeyBinding>,
+}
+
+impl Default for HotkeysConfig {
+    fn default() -> Self {
+        HotkeysConfig {
+            enabled: false,
+            media_keys: true,
+            bindings: Vec::new(),
+        }
+    }
+}
+
+impl HotkeysConfig {
+    /// The configured bindings first, so that they win over the media keys.
+    pub fn get_bindings(&self) -> Vec<HotkeyBinding> {
+        let mut bindings = self.bindings.clone();
+        if self.media_keys {
+            bindings.push(HotkeyBinding::new("KEY_VOLUMEUP", "raise", true));
+            bindings.push(HotkeyBinding::new("KEY_VOLUMEDOWN", "lower", true));
+            bindings.push(HotkeyBinding::new("KEY_MUTE", "mute", false));
+            bindings.push(HotkeyBinding::new("KEY_MICMUTE", "mic_mute", false));
+        }
+        bindings
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct Config {
+    pub feedback: FeedbackKind,
+    pub volume: VolumeConfig,
+    pub pipe: PipeConfig,
+    pub sound: SoundConfig,
+    pub speech: SpeechConfig,
+    pub hotkeys: HotkeysConfig,
+    pub mpris: MprisConfig,
+    pub ducking: DuckingConfig,
+    pub tray: TrayConfig,
+    pub network: NetworkConfig,
+    pub mqtt: MqttConfig,
+}
+
+pub(crate) fn get_config_file_path() -> Result<PathBuf, Error> {
+    let home_folder = env::var("HOME")?;
+    Ok(Path::new(&home_folder).join(".config/audio_tools.toml"))
+}
+
+impl Config {
+    /// Loads the config file. Falls back to the default config if there is none or if it is
+    /// invalid.
+    pub fn load() -> Self {
+        match Config::try_load() {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Failed to load config, using the default one: {}", e);
+                Config::default()
+            }
+        }
+    }
+
+    /// Loads the config file, or the default config if there is none.
+    pub fn try_load() -> Result<Self, Error> {
+        let path = get_config_file_path()?;
+        if !path.exists() {
+            debug!("No config file at {:?}.", path);
+            return Ok(Config::default());
+        }
+        Config::parse(&fs::read_to_string(&path)?)
+    }
+
+    pub(crate) fn parse(content: &str) -> Result<Self, Error> {
+        toml::from_str(content).map_err(|e| Error::toml(content, e))
+    }
+}
@@ -0,0 +1,130 @@
+use std::fs;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::Arc;
+use std::thread;
+
+use log::{error, info};
+
+use crate::command::{execute_command, parse_command, CommandType};
+use crate::config::Config;
+use crate::ducking;
+use crate::error::Error;
+use crate::hotkeys;
+#[cfg(feature = "mqtt")]
+use crate::mqtt;
+use crate::network;
+use crate::schedule;
+use crate::state::get_runtime_file_path;
+use crate::tray;
+
+fn parse_line(line: &str) -> Result<CommandType, Error> {
+    let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+    parse_command(&args)
+}
+
+/// Runs a single command line, e.g. "fade 20 --duration 5000".
+pub fn execute(config: &Config, line: &str) -> Result<(), Error> {
+    let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+    execute_args(config, &args)
+}
+
+/// Runs a command split into its arguments already, e.g. a scheduled one.
+pub fn execute_args(config: &Config, args: &[String]) -> Result<(), Error> {
+    let command = parse_command(args)?;
+    if let Some(error) = command.daemon_error() {
+        return Err(Error::Usage(String::from(error)));
+    }
+    execute_command(config, command)
+}
+
+/// Runs a command line from the network, which only allows the commands that cannot run other
+/// programs.
+pub fn execute_remote(config: &Config, line: &str) -> Result<(), Error> {
+    let command = parse_line(line)?;
+    if !command.is_remote() {
+        return Err(Error::Usage(format!(
+            "\"{}\" cannot be run over the network.",
+            line.trim()
+        )));
+    }
+    execute_command(config, command)
+}
+
+fn handle_client(config: &Config, stream: UnixStream) -> Result<(), Error> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        info!("Daemon received \"{}\".", line);
+        match execute(config, &line) {
+            Ok(()) => writeln!(writer, "ok")?,
+            Err(e) => {
+                error!("Failed to run \"{}\": {}", line, e);
+                writeln!(writer, "error: {}", e)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Accepts commands, one per line, on a socket in the runtime directory. Each client gets its
+/// own thread so that long running commands like fades do not block others.
+pub fn run(config: Config) -> Result<(), Error> {
+    let path = get_runtime_file_path("audio_tools.sock")?;
+    if path.exists() {
+        // Only a socket left behind by a daemon that died may be replaced.
+        if UnixStream::connect(&path).is_ok() {
+            return Err(Error::Usage(format!(
+                "The daemon is already running on {:?}.",
+                path
+            )));
+        }
+        fs::remove_file(&path)?;
+    }
+    let listener = UnixListener::bind(&path)?;
+    info!("Daemon listening on {:?}.", path);
+
+    let config = Arc::new(config);
+    let schedule_config = config.clone();
+    thread::spawn(move || schedule::run(&schedule_config));
+    if config.hotkeys.enabled {
+        let hotkeys_config = config.clone();
+        thread::spawn(move || hotkeys::run(hotkeys_config));
+    }
+    if config.ducking.enabled {
+        let ducking_config = config.clone();
+        thread::spawn(move || ducking::run(ducking_config));
+    }
+    if config.tray.enabled {
+        let tray_config = config.clone();
+        thread::spawn(move || tray::run(tray_config));
+    }
+    if config.network.enabled {
+        let network_config = config.clone();
+        thread::spawn(move || network::run(network_config));
+    }
+    if config.mqtt.enabled {
+        #[cfg(feature = "mqtt")]
+        {
+            let mqtt_config = config.clone();
+            thread::spawn(move || mqtt::run(mqtt_config));
+        }
+        #[cfg(not(feature = "mqtt"))]
+        error!("MQTT is enabled, but audio_tools was built without the mqtt feature.");
+    }
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let config = config.clone();
+                thread::spawn(move || {
+                    handle_client(&config, stream)
+                        .unwrap_or_else(|e| error!("Daemon client failed: {}", e));
+                });
+            }
+            Err(e) => error!("Failed to accept daemon client: {}", e),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,217 @@
+use std::sync::Arc;
+
+use log::{debug, error, info};
+
+use crate::config::{Config, DuckingConfig, DuckingMode};
+use crate::error::Error;
+use crate::feedback::{create_feedback, Feedback};
+use crate::mpris::Mpris;
+use crate::state::{load_ducked_streams, write_ducked_streams, DuckedStream, Restore};
+use crate::volume::{db_to_percent, percent_to_db};
+use crate::volume_control::{follow_changes, list_sink_inputs, Facility, SinkInput, VolumeControl};
+
+struct Ducker {
+    config: DuckingConfig,
+    volume_control: VolumeControl,
+    feedback: Box<dyn Feedback>,
+    /// The media players to pause in cork mode, connected once and kept.
+    mpris: Option<Mpris>,
+    ducked: Vec<DuckedStream>,
+}
+
+impl Ducker {
+    /// Takes over the streams a previous daemon ducked, to restore them once there is no call.
+    fn new(config: &Config) -> Result<Self, Error> {
+        let ducked = load_ducked_streams();
+        if !ducked.is_empty() {
+            info!("{} streams are still ducked from before.", ducked.len());
+        }
+        Ok(Ducker {
+            config: config.ducking.clone(),
+            volume_control: VolumeControl::new()?,
+            feedback: create_feedback(config, None, None),
+            mpris: None,
+            ducked,
+        })
+    }
+
+    /// Keeps the ducked streams in the state, so that they are restored even if the daemon
+    /// stops during a call.
+    fn save(&self) {
+        write_ducked_streams(&self.ducked)
+            .unwrap_or_else(|e| error!("Failed to save the ducked streams: {}", e));
+    }
+
+    fn is_call(&self, stream: &SinkInput) -> bool {
+        self.config.roles.contains(&stream.role)
+            || self
+                .config
+                .applications
+                .iter()
+                .any(|a| a.eq_ignore_ascii_case(&stream.application))
+    }
+
+    fn lowered_volumes(&self, volumes: &[i32]) -> Vec<i32> {
+        volumes
+            .iter()
+            .map(|v| match percent_to_db(*v as f32) {
+                Some(db) => db_to_percent(db - self.config.db).round() as i32,
+                None => 0,
+            })
+            .collect()
+    }
+
+    /// Connects to the media players in cork mode, unless connected already.
+    fn connect_mpris(&mut self) {
+        if self.config.mode != DuckingMode::Cork || self.mpris.is_some() {
+            return;
+        }
+        match Mpris::new() {
+            Ok(mpris) => self.mpris = Some(mpris),
+            Err(e) => error!("Failed to connect to the media players: {}", e),
+        }
+    }
+
+    fn duck(&self, stream: &SinkInput) -> Result<Restore, Error> {
+        let mpris = self.mpris.as_ref();
+        match self.config.mode {
+            DuckingMode::Duck => {
+                self.volume_control
+                    .set_sink_input_volumes(stream.index, &self.lowered_volumes(&stream.volumes))?;
+                Ok(Restore::Volumes(stream.volumes.clone()))
+            }
+            DuckingMode::Cork => {
+                let player = match mpris {
+                    Some(mpris) => mpris.list_players()?.into_iter().find(|p| p.owns(stream)),
+                    None => None,
+                };
+                match (mpris, player) {
+                    (Some(mpris), Some(player)) => {
+                        if self.ducked.iter().any(
+                            |d| matches!(&d.restore, Restore::Resume(b) if *b == player.bus_name),
+                        ) || !player.is_playing()
+                        {
+                            return Ok(Restore::Nothing);
+                        }
+                        mpris.call_player(&player.bus_name, "Pause")?;
+                        Ok(Restore::Resume(player.bus_name))
+                    }
+                    _ if stream.muted => Ok(Restore::Nothing),
+                    _ => {
+                        self.volume_control
+                            .set_sink_input_mute(stream.index, true)?;
+                        Ok(Restore::Unmute)
+                    }
+                }
+            }
+        }
+    }
+
+    fn restore(&self, ducked: &DuckedStream, streams: &[SinkInput]) {
+        let exists = streams
+            .iter()
+            .any(|s| s.index == ducked.index && s.application == ducked.application);
+        let result = match &ducked.restore {
+            Restore::Volumes(volumes) if exists => self
+                .volume_control
+                .set_sink_input_volumes(ducked.index, volumes),
+            Restore::Unmute if exists => {
+                self.volume_control.set_sink_input_mute(ducked.index, false)
+            }
+            Restore::Resume(bus_name) => match &self.mpris {
+                Some(mpris) => mpris.call_player(bus_name, "Play"),
+                None => Err(Error::Unavailable(format!(
+                    "No session bus to resume {}.",
+                    bus_name
+                ))),
+            },
+            _ => Ok(()),
+        };
+        result.unwrap_or_else(|e| error!("Failed to restore stream {}: {}", ducked.index, e));
+    }
+
+    fn notify(&mut self, summary: &str, body: &str) {
+        self.feedback
+            .show_message(summary, body)
+            .unwrap_or_else(|e| error!("Failed to notify: {}", e));
+    }
+
+    /// Ducks all other streams while there is a call and restores them once it ended.
+    fn update(&mut self) -> Result<(), Error> {
+        let streams = list_sink_inputs()?;
+        let calls: Vec<&SinkInput> = streams.iter().filter(|s| self.is_call(s)).collect();
+        self.connect_mpris();
+
+        if calls.is_empty() {
+            if self.ducked.is_empty() {
+                return Ok(());
+            }
+            let ducked: Vec<DuckedStream> = self.ducked.drain(..).collect();
+            info!("Call ended, restoring {} streams.", ducked.len());
+            for stream in &ducked {
+                self.restore(stream, &streams);
+            }
+            self.save();
+            self.notify("Call ended", &format!("Restored {} streams", ducked.len()));
+            return Ok(());
+        }
+
+        let others: Vec<&SinkInput> = streams.iter().filter(|s| !self.is_call(s)).collect();
+        let mut newly_ducked = 0;
+        for stream in others {
+            if self.ducked.iter().any(|d| d.index == stream.index) {
+                continue;
+            }
+            debug!("Ducking stream {} of {}.", stream.index, stream.application);
+            match self.duck(stream) {
+                Ok(restore) => {
+                    if !matches!(restore, Restore::Nothing) {
+                        newly_ducked += 1;
+                    }
+                    self.ducked.push(DuckedStream {
+                        index: stream.index,
+                        application: stream.application.clone(),
+                        restore,
+                    });
+                    self.save();
+                }
+                Err(e) => error!("Failed to duck stream {}: {}", stream.index, e),
+            }
+        }
+        if newly_ducked > 0 {
+            let body = match self.config.mode {
+                DuckingMode::Duck => {
+                    format!("Lowered {} streams by {} dB", newly_ducked, self.config.db)
+                }
+                DuckingMode::Cork => format!("Paused {} streams", newly_ducked),
+            };
+            info!("Call from {}: {}.", calls[0].application, body);
+            let summary = format!("Call from {}", calls[0].application);
+            self.notify(&summary, &body);
+        }
+        Ok(())
+    }
+}
+
+/// Follows the streams and ducks the others during calls.
+pub fn run(config: Arc<Config>) {
+    let mut ducker = match Ducker::new(&config) {
+        Ok(ducker) => ducker,
+        Err(e) => {
+            error!("Failed to start ducking: {}", e);
+            return;
+        }
+    };
+    info!("Ducking streams during calls.");
+    let changes = follow_changes(&[Facility::SinkInput]);
+    loop {
+        ducker
+            .update()
+            .unwrap_or_else(|e| error!("Failed to duck streams: {}", e));
+        if changes.recv().is_err() {
+            return;
+        }
+        // A single change often comes as several events.
+        while changes.try_recv().is_ok() {}
+    }
+}
@@ -0,0 +1,139 @@
+use std::env;
+use std::fmt;
+use std::io;
+
+/// Everything that can go wrong in this crate.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// A program of the sound server, e.g. pactl or amixer, is not installed, or the sound
+    /// server is not running.
+    BackendNotFound(String),
+    /// A program ran, but reported an error.
+    CommandFailed { command: String, stderr: String },
+    /// A line of the output of a program, of a file or an argument could not be parsed.
+    ParseError { line: String, message: String },
+    /// Showing a notification failed.
+    NotificationFailed(String),
+    /// There is no default sink to act on.
+    NoActiveSink,
+    /// Reading or writing a file failed.
+    Io(io::Error),
+    /// Decoding or playing a sound failed.
+    Audio(String),
+    /// Talking to another program on D-Bus failed.
+    Dbus(String),
+    /// The command line is invalid, or the command cannot be run right now.
+    Usage(String),
+    /// Something the command needs is not there, e.g. a device, a player or a scene.
+    Unavailable(String),
+}
+
+impl Error {
+    pub(crate) fn parse<L: AsRef<str>, M: fmt::Display>(line: L, message: M) -> Self {
+        Error::ParseError {
+            line: line.as_ref().trim().to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    /// A TOML error, with the line of the content it points to.
+    pub(crate) fn toml(content: &str, e: toml::de::Error) -> Self {
+        let line = e
+            .line_col()
+            .and_then(|(line, _)| content.lines().nth(line))
+            .unwrap_or("");
+        Error::parse(line, e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::BackendNotFound(message) => write!(
+                f,
+                "Sound server not available: {}. Is PulseAudio or pipewire-pulse installed and running?",
+                message
+            ),
+            Error::CommandFailed { command, stderr } if stderr.is_empty() => {
+                write!(f, "\"{}\" failed.", command)
+            }
+            Error::CommandFailed { command, stderr } => {
+                write!(f, "\"{}\" failed: {}", command, stderr)
+            }
+            Error::ParseError { line, message } if line.is_empty() => {
+                write!(f, "Parse error: {}", message)
+            }
+            Error::ParseError { line, message } => {
+                write!(f, "Failed to parse \"{}\": {}", line, message)
+            }
+            Error::NotificationFailed(message) => write!(f, "Notification failed: {}", message),
+            Error::NoActiveSink => write!(f, "There is no active sink."),
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Audio(message) => write!(f, "Audio error: {}", message),
+            Error::Dbus(message) => write!(f, "D-Bus error: {}", message),
+            Error::Usage(message) => write!(f, "{}", message),
+            Error::Unavailable(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<env::VarError> for Error {
+    fn from(e: env::VarError) -> Self {
+        Error::Unavailable(format!("Environment variable: {}", e))
+    }
+}
+
+/// Conversions of errors that only carry a message.
+macro_rules! convert_error {
+    ($variant:ident, $($error:ty),+) => {
+        $(
+            impl From<$error> for Error {
+                fn from(e: $error) -> Self {
+                    Error::$variant(e.to_string())
+                }
+            }
+        )+
+    };
+}
+
+// Neither comes from parsing input: the patterns are fixed and scenes are only written.
+impl From<regex::Error> for Error {
+    fn from(e: regex::Error) -> Self {
+        Error::parse("", e)
+    }
+}
+
+impl From<toml::ser::Error> for Error {
+    fn from(e: toml::ser::Error) -> Self {
+        Error::parse("", e)
+    }
+}
+
+convert_error!(Dbus, dbus::Error, dbus::arg::TypeMismatchError);
+convert_error!(
+    Audio,
+    cpal::DevicesError,
+    cpal::DefaultStreamConfigError,
+    cpal::SupportedStreamConfigsError,
+    cpal::BuildStreamError,
+    cpal::PlayStreamError,
+    hound::Error,
+    lewton::VorbisError,
+    claxon::Error
+);
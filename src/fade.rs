@@ -0,0 +1,116 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::debug;
+
+use crate::error::Error;
+use crate::state::get_runtime_file_path;
+use crate::volume::VolumeInfo;
+
+/// How often a fade sets the volume.
+pub const STEP_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A fade of the channel volumes from one factor to another.
+pub struct Ramp {
+    pub channels: Vec<i32>,
+    pub from: f32,
+    pub to: f32,
+    pub num_steps: u32,
+}
+
+impl Ramp {
+    /// Fades from the current volume to the target, keeping the balance.
+    pub fn to_target(volume: &VolumeInfo, target: i32, duration: Duration) -> Self {
+        match volume.volume {
+            0 => Ramp::new(volume.volumes_scaled_to(target), 0.0, 1.0, duration),
+            current => Ramp::new(
+                volume.volumes_scaled_to(current),
+                1.0,
+                target as f32 / current as f32,
+                duration,
+            ),
+        }
+    }
+
+    pub fn new(channels: Vec<i32>, from: f32, to: f32, duration: Duration) -> Self {
+        Ramp {
+            channels,
+            from,
+            to,
+            num_steps: (duration.as_millis() / STEP_INTERVAL.as_millis()).max(1) as u32,
+        }
+    }
+
+    /// Channel volumes after the given step, from 1 to `num_steps`.
+    pub fn volumes_at(&self, step: u32) -> Vec<i32> {
+        let factor = self.from + (self.to - self.from) * step as f32 / self.num_steps as f32;
+        self.channels
+            .iter()
+            .map(|c| (*c as f32 * factor).round() as i32)
+            .collect()
+    }
+}
+
+/// Only the most recently started fade keeps running, in this or any other process.
+pub struct FadeToken {
+    path: PathBuf,
+    id: String,
+}
+
+impl FadeToken {
+    fn new() -> Result<Self, Error> {
+        let path = get_runtime_file_path("audio_tools_fade")?;
+        let id = format!(
+            "{}-{:?}-{}",
+            process::id(),
+            thread::current().id(),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos()
+        );
+        debug!("Starting fade {}.", id);
+        Ok(Self { path, id })
+    }
+
+    /// Takes over from any fade that is still running.
+    pub fn acquire() -> Result<Self, Error> {
+        let token = Self::new()?;
+        fs::write(&token.path, &token.id)?;
+        Ok(token)
+    }
+
+    pub fn is_current(&self) -> bool {
+        fs::read_to_string(&self.path)
+            .map(|id| id == self.id)
+            .unwrap_or(false)
+    }
+
+    /// Same as [`FadeToken::acquire`], without blocking.
+    #[cfg(feature = "async")]
+    pub async fn acquire_async() -> Result<Self, Error> {
+        let token = Self::new()?;
+        tokio::fs::write(&token.path, &token.id).await?;
+        Ok(token)
+    }
+
+    /// Same as [`FadeToken::is_current`], without blocking.
+    #[cfg(feature = "async")]
+    pub async fn is_current_async(&self) -> bool {
+        tokio::fs::read_to_string(&self.path)
+            .await
+            .map(|id| id == self.id)
+            .unwrap_or(false)
+    }
+}
+
+impl Drop for FadeToken {
+    fn drop(&mut self) {
+        if self.is_current() {
+            fs::remove_file(&self.path).unwrap_or(());
+        }
+    }
+}
@@ -0,0 +1,362 @@
+use std::fs::OpenOptions;
+use std::io;
+use std::io::prelude::*;
+use std::os::unix::fs::OpenOptionsExt;
+
+use log::{debug, warn};
+
+use crate::config::{Config, FeedbackKind, PipeConfig, PipeFormat};
+use crate::error::Error;
+use crate::notification::{
+    build_level_blocks, LevelNotification, MessageNotification, MicrophoneNotification,
+    SinkNotificaton, VolumeNotification,
+};
+use crate::volume::VolumeInfo;
+use crate::volume_control::Sink;
+
+/// A way of telling the user about volume and interface changes.
+pub trait Feedback {
+    fn show_volume(&mut self, volume_info: &VolumeInfo) -> Result<(), Error>;
+
+    fn show_interface_change_start(&mut self) -> Result<(), Error>;
+
+    /// Shows the interfaces and what is playing on the active one.
+    fn show_interfaces(
+        &mut self,
+        interfaces: &[Sink],
+        now_playing: Option<&str>,
+    ) -> Result<(), Error>;
+
+    /// Shows a live level between 0 and 100, called repeatedly while metering.
+    fn show_level(&mut self, source: &str, level: i32) -> Result<(), Error>;
+
+    /// Called once metering is done.
+    fn finish_level(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Shows a message that is not about a single volume or interface change.
+    fn show_message(&mut self, summary: &str, body: &str) -> Result<(), Error>;
+
+    /// Shows whether push-to-talk has the microphone live.
+    fn show_microphone(&mut self, source: &str, live: bool) -> Result<(), Error>;
+
+    /// IDs of the volume and the sink notification, if the frontend uses any.
+    fn get_notification_ids(&self) -> (Option<u32>, Option<u32>) {
+        (None, None)
+    }
+}
+
+/// Freedesktop notifications.
+pub struct NotificationFeedback {
+    volume_notification: VolumeNotification,
+    sink_notification: SinkNotificaton,
+    level_notification: LevelNotification,
+    message_notification: MessageNotification,
+    microphone_notification: MicrophoneNotification,
+}
+
+impl NotificationFeedback {
+    pub fn new(
+        volume_notification_id: Option<u32>,
+        sink_notification_id: Option<u32>,
+        show_db: bool,
+    ) -> Self {
+        NotificationFeedback {
+            volume_notification: VolumeNotification::new(volume_notification_id, show_db),
+            sink_notification: SinkNotificaton::new(sink_notification_id),
+            level_notification: LevelNotification::new(),
+            message_notification: MessageNotification::new(),
+            microphone_notification: MicrophoneNotification::new(),
+        }
+    }
+
+    pub fn is_available() -> bool {
+        notify_rust::get_server_information().is_ok()
+    }
+}
+
+impl Feedback for NotificationFeedback {
+    fn show_volume(&mut self, volume_info: &VolumeInfo) -> Result<(), Error> {
+        self.volume_notification.notify(volume_info)
+    }
+
+    fn show_interface_change_start(&mut self) -> Result<(), Error> {
+        self.sink_notification.notify_start()
+    }
+
+    fn show_interfaces(
+        &mut self,
+        interfaces: &[Sink],
+        now_playing: Option<&str>,
+    ) -> Result<(), Error> {
+        self.sink_notification.notify(interfaces, now_playing)
+    }
+
+    fn show_level(&mut self, source: &str, level: i32) -> Result<(), Error> {
+        self.level_notification.notify(source, level)
+    }
+
+    fn show_message(&mut self, summary: &str, body: &str) -> Result<(), Error> {
+        self.message_notification.notify(summary, body)
+    }
+
+    fn show_microphone(&mut self, source: &str, live: bool) -> Result<(), Error> {
+        self.microphone_notification.notify(source, live)
+    }
+
+    fn get_notification_ids(&self) -> (Option<u32>, Option<u32>) {
+        (
+            self.volume_notification.get_id(),
+            self.sink_notification.get_id(),
+        )
+    }
+}
+
+/// Plain text on stdout.
+pub struct TerminalFeedback;
+
+impl Feedback for TerminalFeedback {
+    fn show_volume(&mut self, volume_info: &VolumeInfo) -> Result<(), Error> {
+        println!(
+            "Volume: {}%{}",
+            volume_info.volume,
+            if volume_info.muted { " (muted)" } else { "" }
+        );
+        if !volume_info.is_balanced() {
+            println!("Balance: {}", volume_info.balance_string());
+            for channel in &volume_info.channels {
+                println!("  {}: {}%", channel.name, channel.volume);
+            }
+        }
+        Ok(())
+    }
+
+    fn show_interface_change_start(&mut self) -> Result<(), Error> {
+        println!("Changing input...");
+        Ok(())
+    }
+
+    fn show_interfaces(
+        &mut self,
+        interfaces: &[Sink],
+        now_playing: Option<&str>,
+    ) -> Result<(), Error> {
+        for interface in interfaces {
+            let marker = if interface.active { "→" } else { " " };
+            println!("{} {}", marker, interface.name);
+        }
+        if let Some(now_playing) = now_playing {
+            println!("Playing: {}", now_playing);
+        }
+        Ok(())
+    }
+
+    fn show_level(&mut self, source: &str, level: i32) -> Result<(), Error> {
+        let (full_blocks, _) = build_level_blocks(level, false);
+        // Overwrite the previous status line.
+        print!("\r{}: {:<20} {:3}%", source, full_blocks, level);
+        io::stdout().flush()?;
+        Ok(())
+    }
+
+    fn finish_level(&mut self) -> Result<(), Error> {
+        println!();
+        Ok(())
+    }
+
+    fn show_message(&mut self, summary: &str, body: &str) -> Result<(), Error> {
+        println!("{}", summary);
+        if !body.is_empty() {
+            println!("{}", body);
+        }
+        Ok(())
+    }
+
+    fn show_microphone(&mut self, source: &str, live: bool) -> Result<(), Error> {
+        println!(
+            "Microphone {}: {}",
+            if live { "live" } else { "muted" },
+            source
+        );
+        Ok(())
+    }
+}
+
+/// Writes the volume to a named pipe in the format wob or xob reads.
+pub struct PipeFeedback {
+    config: PipeConfig,
+}
+
+impl PipeFeedback {
+    pub fn new(config: PipeConfig) -> Self {
+        PipeFeedback { config }
+    }
+
+    fn build_line(&self, value: i32, muted: bool) -> String {
+        match (self.config.format, muted) {
+            (PipeFormat::Wob, true) if !self.config.muted_style.is_empty() => {
+                format!("{} {}\n", value, self.config.muted_style)
+            }
+            (PipeFormat::Xob, true) => format!("{}!\n", value),
+            _ => format!("{}\n", value),
+        }
+    }
+
+    fn write_line(&self, line: &str) -> Result<(), Error> {
+        debug!("Writing {:?} to {:?}.", line, self.config.path);
+        // Opening a pipe without a reader would block forever, fail instead.
+        let mut pipe = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&self.config.path)?;
+        pipe.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl Feedback for PipeFeedback {
+    fn show_volume(&mut self, volume_info: &VolumeInfo) -> Result<(), Error> {
+        self.write_line(&self.build_line(volume_info.volume, volume_info.muted))
+    }
+
+    fn show_interface_change_start(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn show_interfaces(
+        &mut self,
+        _interfaces: &[Sink],
+        _now_playing: Option<&str>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn show_level(&mut self, _source: &str, level: i32) -> Result<(), Error> {
+        self.write_line(&self.build_line(level, false))
+    }
+
+    fn show_message(&mut self, _summary: &str, _body: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn show_microphone(&mut self, _source: &str, _live: bool) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Uses the terminal whenever the primary frontend fails.
+struct FeedbackWithFallback {
+    primary: Box<dyn Feedback>,
+    fallback: TerminalFeedback,
+    /// Whether the terminal shows a level that still needs to be finished.
+    fallback_level: bool,
+}
+
+impl FeedbackWithFallback {
+    /// Runs `primary`, falling back to the terminal with `fallback` if it fails.
+    fn with_fallback<T>(
+        &mut self,
+        primary: impl FnOnce(&mut dyn Feedback) -> Result<T, Error>,
+        fallback: impl FnOnce(&mut TerminalFeedback) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        primary(self.primary.as_mut()).or_else(|e| {
+            warn!("Feedback failed, falling back to the terminal: {}", e);
+            fallback(&mut self.fallback)
+        })
+    }
+}
+
+impl Feedback for FeedbackWithFallback {
+    fn show_volume(&mut self, volume_info: &VolumeInfo) -> Result<(), Error> {
+        self.with_fallback(
+            |primary| primary.show_volume(volume_info),
+            |fallback| fallback.show_volume(volume_info),
+        )
+    }
+
+    fn show_interface_change_start(&mut self) -> Result<(), Error> {
+        self.with_fallback(
+            |primary| primary.show_interface_change_start(),
+            |fallback| fallback.show_interface_change_start(),
+        )
+    }
+
+    fn show_interfaces(
+        &mut self,
+        interfaces: &[Sink],
+        now_playing: Option<&str>,
+    ) -> Result<(), Error> {
+        self.with_fallback(
+            |primary| primary.show_interfaces(interfaces, now_playing),
+            |fallback| fallback.show_interfaces(interfaces, now_playing),
+        )
+    }
+
+    fn show_level(&mut self, source: &str, level: i32) -> Result<(), Error> {
+        let mut fell_back = false;
+        let result = self.with_fallback(
+            |primary| primary.show_level(source, level),
+            |fallback| {
+                fell_back = true;
+                fallback.show_level(source, level)
+            },
+        );
+        self.fallback_level |= fell_back;
+        result
+    }
+
+    fn finish_level(&mut self) -> Result<(), Error> {
+        let result = self.primary.finish_level();
+        if std::mem::take(&mut self.fallback_level) {
+            self.fallback.finish_level()?;
+        }
+        result
+    }
+
+    fn show_message(&mut self, summary: &str, body: &str) -> Result<(), Error> {
+        self.with_fallback(
+            |primary| primary.show_message(summary, body),
+            |fallback| fallback.show_message(summary, body),
+        )
+    }
+
+    fn show_microphone(&mut self, source: &str, live: bool) -> Result<(), Error> {
+        self.with_fallback(
+            |primary| primary.show_microphone(source, live),
+            |fallback| fallback.show_microphone(source, live),
+        )
+    }
+
+    fn get_notification_ids(&self) -> (Option<u32>, Option<u32>) {
+        self.primary.get_notification_ids()
+    }
+}
+
+pub fn create_feedback(
+    config: &Config,
+    volume_notification_id: Option<u32>,
+    sink_notification_id: Option<u32>,
+) -> Box<dyn Feedback> {
+    let primary: Box<dyn Feedback> = match config.feedback {
+        FeedbackKind::Notification => {
+            if NotificationFeedback::is_available() {
+                Box::new(NotificationFeedback::new(
+                    volume_notification_id,
+                    sink_notification_id,
+                    config.volume.show_db,
+                ))
+            } else {
+                warn!("No notification service available, using the terminal instead.");
+                return Box::new(TerminalFeedback);
+            }
+        }
+        FeedbackKind::Terminal => return Box::new(TerminalFeedback),
+        FeedbackKind::Pipe => Box::new(PipeFeedback::new(config.pipe.clone())),
+    };
+    Box::new(FeedbackWithFallback {
+        primary,
+        fallback: TerminalFeedback,
+        fallback_level: false,
+    })
+}
@@ -0,0 +1,201 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use evdev::{Device, InputEventKind, Key};
+use log::{debug, error, info, warn};
+
+use crate::config::{Config, HotkeyBinding};
+use crate::daemon;
+use crate::error::Error;
+
+struct Binding {
+    key: Key,
+    modifiers: Vec<Key>,
+    command: String,
+    repeat: bool,
+}
+
+fn parse_key(name: &str) -> Option<Key> {
+    match name.parse::<Key>() {
+        Ok(key) => Some(key),
+        Err(_) => {
+            error!("Unknown key \"{}\" in hotkey binding.", name);
+            None
+        }
+    }
+}
+
+fn parse_binding(binding: &HotkeyBinding) -> Option<Binding> {
+    let key = parse_key(&binding.key)?;
+    let modifiers = binding
+        .modifiers
+        .iter()
+        .map(|m| parse_key(m))
+        .collect::<Option<Vec<Key>>>()?;
+    Some(Binding {
+        key,
+        modifiers,
+        command: binding.command.clone(),
+        repeat: binding.repeat,
+    })
+}
+
+/// Modifiers held on any of the devices. Media keys often come from another device than the
+/// keyboard, e.g. its "Consumer Control" node, so a binding like Shift+KEY_VOLUMEUP needs the
+/// modifiers of all devices.
+#[derive(Clone, Default)]
+struct HeldModifiers(Arc<Mutex<HashSet<(PathBuf, Key)>>>);
+
+impl HeldModifiers {
+    fn set(&self, path: &Path, key: Key, held: bool) {
+        let mut held_modifiers = self.0.lock().unwrap();
+        if held {
+            held_modifiers.insert((path.to_path_buf(), key));
+        } else {
+            held_modifiers.remove(&(path.to_path_buf(), key));
+        }
+    }
+
+    fn contains(&self, key: Key) -> bool {
+        self.0.lock().unwrap().iter().any(|(_, k)| *k == key)
+    }
+
+    /// Forgets the modifiers of a device that is gone.
+    fn remove_device(&self, path: &Path) {
+        self.0.lock().unwrap().retain(|(p, _)| p != path);
+    }
+}
+
+/// Whether the device has a bound key or a modifier of a binding.
+fn supports_any_key(device: &Device, bindings: &[Binding]) -> bool {
+    match device.supported_keys() {
+        Some(keys) => bindings
+            .iter()
+            .any(|b| keys.contains(b.key) || b.modifiers.iter().any(|m| keys.contains(*m))),
+        None => false,
+    }
+}
+
+/// Runs the commands bound to the keys pressed on a device until it is unplugged, and keeps
+/// track of the modifiers held on it.
+fn watch_device(
+    config: &Config,
+    bindings: &[Binding],
+    path: &Path,
+    mut device: Device,
+    held_modifiers: &HeldModifiers,
+) -> Result<(), Error> {
+    let is_modifier = |key: Key| bindings.iter().any(|b| b.modifiers.contains(&key));
+    // Modifiers held down already when the device was opened.
+    for key in device.get_key_state()?.iter().filter(|k| is_modifier(*k)) {
+        held_modifiers.set(path, key, true);
+    }
+    loop {
+        // Press (1) and auto-repeat (2), but not release (0).
+        let mut presses: Vec<(Key, bool)> = Vec::new();
+        for event in device.fetch_events()? {
+            if let InputEventKind::Key(key) = event.kind() {
+                if is_modifier(key) {
+                    held_modifiers.set(path, key, event.value() > 0);
+                }
+                if event.value() > 0 {
+                    presses.push((key, event.value() == 2));
+                }
+            }
+        }
+        for (key, repeated) in presses {
+            // The binding with the most modifiers, e.g. Shift+KEY_VOLUMEUP over KEY_VOLUMEUP.
+            let binding = bindings
+                .iter()
+                .filter(|b| b.key == key && b.modifiers.iter().all(|m| held_modifiers.contains(*m)))
+                .min_by_key(|b| Reverse(b.modifiers.len()))
+                .filter(|b| b.repeat || !repeated);
+            if let Some(binding) = binding {
+                debug!("Hotkey {:?} runs \"{}\".", key, binding.command);
+                let config = config.clone();
+                let command = binding.command.clone();
+                thread::spawn(move || {
+                    daemon::execute(&config, &command)
+                        .unwrap_or_else(|e| error!("Hotkey command \"{}\" failed: {}", command, e));
+                });
+            }
+        }
+    }
+}
+
+/// Listens for the bound keys on all input devices, directly through evdev. This works on any
+/// Wayland compositor, X server or TTY, but needs read access to /dev/input, usually through the
+/// "input" group. Devices plugged in later are picked up by scanning regularly.
+pub fn run(config: Arc<Config>) {
+    const SCAN_INTERVAL: Duration = Duration::from_secs(10);
+    let bindings: Arc<Vec<Binding>> = Arc::new(
+        config
+            .hotkeys
+            .get_bindings()
+            .iter()
+            .filter_map(parse_binding)
+            .collect(),
+    );
+    if bindings.is_empty() {
+        warn!("Hotkeys are enabled, but nothing is bound.");
+        return;
+    }
+
+    let watched_devices: Arc<Mutex<HashSet<PathBuf>>> = Arc::new(Mutex::new(HashSet::new()));
+    let held_modifiers = HeldModifiers::default();
+    let mut first_scan = true;
+    loop {
+        for (path, device) in evdev::enumerate() {
+            if !supports_any_key(&device, &bindings)
+                || !watched_devices.lock().unwrap().insert(path.clone())
+            {
+                continue;
+            }
+            info!(
+                "Listening for hotkeys on {:?} ({}).",
+                path,
+                device.name().unwrap_or("unnamed")
+            );
+            let config = config.clone();
+            let bindings = bindings.clone();
+            let watched_devices = watched_devices.clone();
+            let held_modifiers = held_modifiers.clone();
+            thread::spawn(move || {
+                if let Err(e) = watch_device(&config, &bindings, &path, device, &held_modifiers) {
+                    info!("Stopped listening for hotkeys on {:?}: {}", path, e);
+                }
+                held_modifiers.remove_device(&path);
+                watched_devices.lock().unwrap().remove(&path);
+            });
+        }
+        if first_scan && watched_devices.lock().unwrap().is_empty() {
+            warn!("No readable input device has the bound keys. Is the user in the input group?");
+        }
+        first_scan = false;
+        thread::sleep(SCAN_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shares_modifiers_between_devices() {
+        let keyboard = Path::new("/dev/input/event3");
+        let consumer_control = Path::new("/dev/input/event4");
+        let held_modifiers = HeldModifiers::default();
+        held_modifiers.set(keyboard, Key::KEY_LEFTSHIFT, true);
+        held_modifiers.set(consumer_control, Key::KEY_LEFTSHIFT, true);
+        assert!(held_modifiers.contains(Key::KEY_LEFTSHIFT));
+        held_modifiers.set(keyboard, Key::KEY_LEFTSHIFT, false);
+        assert!(held_modifiers.contains(Key::KEY_LEFTSHIFT));
+        held_modifiers.remove_device(consumer_control);
+        assert!(!held_modifiers.contains(Key::KEY_LEFTSHIFT));
+        assert!(!held_modifiers.contains(Key::KEY_LEFTCTRL));
+    }
+}
@@ -1,88 +1,615 @@
-use failure::Error;
-use log::error;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::notification::{SinkNotificaton, SoundPlayer, VolumeNotification};
-use crate::volume::VolumeInfo;
-use crate::volume_control::VolumeControl;
+use log::{debug, error, info};
+
+pub use crate::command::SelectTarget;
+use crate::config::{Config, FeedbackKind, MprisConfig, StepMode, VolumeConfig};
+use crate::error::Error;
+use crate::fade::{FadeToken, Ramp, STEP_INTERVAL};
+use crate::feedback::{create_feedback, Feedback, PipeFeedback};
+use crate::mpris::{Mpris, PlayerAction};
+use crate::notification::{SoundEvent, SoundPlayer};
+use crate::picker::{self, Choice, Chooser};
+use crate::ptt::{self, LiveSource};
+use crate::scene::Scene;
+use crate::sound::PeakMeter;
+use crate::speech::Speaker;
+use crate::state::{take_paused_players, write_paused_players};
+use crate::tui;
+use crate::volume::{
+    cap_channel_volumes, cap_volume, cap_volume_change, BalanceDirection, VolumeInfo,
+};
+use crate::volume_control::{
+    get_default_source, is_source_muted, list_cards, list_sink_inputs, list_sources, Sink, Source,
+    VolumeControl,
+};
+
+/// The sinks to pick from, by name or description.
+fn sink_choices(sinks: &[Sink]) -> Vec<Choice> {
+    sinks
+        .iter()
+        .map(|s| Choice {
+            name: s.sink_name.clone(),
+            label: s.name.clone(),
+            active: s.active,
+        })
+        .collect()
+}
 
 pub struct Interface {
     volume_control: VolumeControl,
-    volume_notification: VolumeNotification,
-    sink_notification: SinkNotificaton,
+    feedback: Box<dyn Feedback>,
+    pipe_feedback: Option<PipeFeedback>,
     sound_player: SoundPlayer,
+    speaker: Option<Speaker>,
+    volume_config: VolumeConfig,
+    mpris_config: MprisConfig,
 }
 
 impl Interface {
-    pub fn new(volume_notification_id: Option<u32>, sink_notification_id: Option<u32>) -> Self {
-        Interface {
-            volume_control: VolumeControl::new().expect("Failed to create volume notification."),
-            volume_notification: VolumeNotification::new(volume_notification_id),
-            sink_notification: SinkNotificaton::new(sink_notification_id),
-            sound_player: SoundPlayer::new(),
-        }
+    pub fn new(
+        config: &Config,
+        volume_notification_id: Option<u32>,
+        sink_notification_id: Option<u32>,
+    ) -> Result<Self, Error> {
+        Ok(Interface {
+            volume_control: VolumeControl::new()?,
+            feedback: create_feedback(config, volume_notification_id, sink_notification_id),
+            pipe_feedback: if config.pipe.enabled && config.feedback != FeedbackKind::Pipe {
+                Some(PipeFeedback::new(config.pipe.clone()))
+            } else {
+                None
+            },
+            sound_player: SoundPlayer::new(&config.sound),
+            speaker: Speaker::new(&config.speech),
+            volume_config: config.volume.clone(),
+            mpris_config: config.mpris.clone(),
+        })
     }
 
     pub fn get_notification_ids(&self) -> (Option<u32>, Option<u32>) {
-        (self.volume_notification.get_id(), self.sink_notification.get_id())
+        self.feedback.get_notification_ids()
     }
 
     pub fn show_volume_notification(&mut self, always_play_sound: bool) -> Result<(), Error> {
-        match VolumeInfo::get_volume() {
-            Err(e) => error!("Failed to get volume status: {}", e),
-            Ok(volume) => {
-                self.volume_notification
-                    .notify(&volume)
-                    .unwrap_or_else(|e| eprintln!("Failed to notify: {}", e));
-            }
-        }
-        self.play_sound(always_play_sound)?;
+        self.notify_volume()?;
+        self.play_sound(SoundEvent::VolumeChange, always_play_sound)?;
         Ok(())
     }
 
-    fn play_sound(&mut self, always_play_sound: bool) -> Result<(), Error> {
+    fn notify_volume(&mut self) -> Result<VolumeInfo, Error> {
+        let volume = VolumeInfo::get_volume()?;
+        self.feedback.show_volume(&volume)?;
+        if let Some(pipe_feedback) = &mut self.pipe_feedback {
+            // The pipe is only shown next to the feedback, e.g. while wob is running.
+            pipe_feedback
+                .show_volume(&volume)
+                .unwrap_or_else(|e| error!("Failed to write to the pipe: {}", e));
+        }
+        Ok(volume)
+    }
+
+    fn play_sound(&mut self, event: SoundEvent, always_play_sound: bool) -> Result<(), Error> {
         self.volume_control = VolumeControl::new()?;
         if let Some(active_interface) = &self.volume_control.active_interface {
             self.sound_player
-                .play_sound(&active_interface, always_play_sound);
+                .play_sound(active_interface, event, always_play_sound);
         }
         Ok(())
     }
 
-    pub fn change_volume(&mut self, amount: i32) -> Result<(), Error> {
+    /// Raises (positive) or lowers (negative) the volume by a number of configured steps.
+    pub fn step_volume(&mut self, steps: i32) -> Result<(), Error> {
+        let step = self.volume_config.get_step();
+        if self.volume_config.step_mode == StepMode::Linear {
+            return self.change_volume(steps * step.round() as i32);
+        }
+        let volume = VolumeInfo::get_volume()?;
+        let target = volume.volume_after_steps(self.volume_config.step_mode, step, steps);
+        // Always move by at least one percent, rounding would get stuck otherwise.
+        let amount = match (target - volume.volume as f32).round() as i32 {
+            0 => steps.signum(),
+            amount => amount,
+        };
+        debug!(
+            "Stepping volume from {}% to {:.1}% ({:?}).",
+            volume.volume, target, self.volume_config.step_mode
+        );
+        self.change_volume(amount)
+    }
+
+    /// Sets the channel volumes of the active sink, within the cap of the active profile.
+    fn set_channel_volumes(&self, volumes: &[i32]) -> Result<(), Error> {
+        let max_volume = self.volume_config.get_current_max_volume();
         self.volume_control
-            .change_volume(amount)
-            .unwrap_or_else(|e| error!("Failed to change volume: {}", e));
+            .set_channel_volumes(&cap_channel_volumes(volumes, max_volume))
+    }
+
+    pub fn change_volume(&mut self, amount: i32) -> Result<(), Error> {
+        let max_volume = self.volume_config.get_current_max_volume();
+        let amount = match max_volume {
+            Some(_) if amount > 0 => {
+                cap_volume_change(VolumeInfo::get_volume()?.volume, amount, max_volume)
+            }
+            _ => amount,
+        };
+        self.volume_control.change_volume(amount)?;
+        let volume = self.notify_volume()?;
+        let event = if amount >= 0 && volume.volume >= max_volume.unwrap_or(100) {
+            SoundEvent::MaxVolume
+        } else {
+            SoundEvent::VolumeChange
+        };
+        self.play_sound(event, false)?;
+        Ok(())
+    }
+
+    /// Moves the balance by the amount in percent towards a side, or centers it.
+    pub fn change_balance(
+        &mut self,
+        direction: BalanceDirection,
+        amount: i32,
+    ) -> Result<(), Error> {
+        let volume = VolumeInfo::get_volume()?;
+        let amount = amount as f32 / 100.0;
+        let balance = match direction {
+            BalanceDirection::Left => volume.balance - amount,
+            BalanceDirection::Right => volume.balance + amount,
+            BalanceDirection::Center => 0.0,
+        };
+        debug!("Changing balance from {} to {}.", volume.balance, balance);
+        self.set_channel_volumes(&volume.volumes_with_balance(balance))?;
         self.show_volume_notification(false)?;
         Ok(())
     }
 
     pub fn toggle_mute(&mut self) -> Result<(), Error> {
-        self.volume_control
-            .toggle_mute()
-            .unwrap_or_else(|e| error!("Failed to toggle mute: {}", e));
-        self.show_volume_notification(false)?;
+        self.volume_control.toggle_mute()?;
+        let event = if self.notify_volume()?.muted {
+            SoundEvent::Mute
+        } else {
+            SoundEvent::Unmute
+        };
+        self.update_players(event)?;
+        self.play_sound(event, false)?;
 
         Ok(())
     }
 
+    /// Pauses the playing media players on mute and resumes them on unmute, if configured.
+    fn update_players(&self, event: SoundEvent) -> Result<(), Error> {
+        if !self.mpris_config.pause_on_mute {
+            return Ok(());
+        }
+        let mpris = Mpris::new()?;
+        match event {
+            SoundEvent::Mute => write_paused_players(&mpris.pause_playing()?),
+            SoundEvent::Unmute => {
+                mpris.resume(&take_paused_players()?);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn control_player(&mut self, action: PlayerAction) -> Result<(), Error> {
+        Mpris::new()?.control(action)
+    }
+
+    /// What is playing on the active interface, e.g. "Spotify: Artist - Title".
+    fn get_now_playing(&self) -> Option<String> {
+        let active_interface = self.volume_control.active_interface.as_ref()?;
+        let result = Mpris::new()
+            .and_then(|mpris| mpris.now_playing(active_interface.index, &list_sink_inputs()?));
+        match result {
+            Ok(player) => player.map(|p| p.describe()),
+            Err(e) => {
+                error!("Failed to find the playing media player: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Gradually changes the volume to the target, keeping the balance.
+    pub fn fade(&mut self, target: i32, duration: Duration) -> Result<(), Error> {
+        let target = cap_volume(target, self.volume_config.get_current_max_volume());
+        let token = FadeToken::acquire()?;
+        let volume = VolumeInfo::get_volume()?;
+        info!("Fading from {}% to {}%.", volume.volume, target);
+        self.ramp(&token, &Ramp::to_target(&volume, target, duration))?;
+        Ok(())
+    }
+
+    /// Fades out before muting or fades in after unmuting.
+    pub fn toggle_mute_ramped(&mut self, duration: Duration) -> Result<(), Error> {
+        let token = FadeToken::acquire()?;
+        let volume = VolumeInfo::get_volume()?;
+        let channels = volume.volumes_scaled_to(volume.volume);
+        if volume.muted {
+            self.set_channel_volumes(&volume.volumes_scaled_to(0))?;
+            self.volume_control.toggle_mute()?;
+            if !self.ramp(&token, &Ramp::new(channels, 0.0, 1.0, duration))? {
+                return Ok(());
+            }
+        } else {
+            if !self.ramp(&token, &Ramp::new(channels.clone(), 1.0, 0.0, duration))? {
+                return Ok(());
+            }
+            self.volume_control.toggle_mute()?;
+            // Restore the volume so that unmuting goes back to it.
+            self.set_channel_volumes(&channels)?;
+        }
+        let event = if self.notify_volume()?.muted {
+            SoundEvent::Mute
+        } else {
+            SoundEvent::Unmute
+        };
+        self.update_players(event)?;
+        self.play_sound(event, false)?;
+        Ok(())
+    }
+
+    /// Steps the channel volumes along the ramp. Returns false if a newer fade cancelled this
+    /// one.
+    fn ramp(&mut self, token: &FadeToken, ramp: &Ramp) -> Result<bool, Error> {
+        let mut last_volumes = Vec::new();
+        for step in 1..=ramp.num_steps {
+            thread::sleep(STEP_INTERVAL);
+            if !token.is_current() {
+                info!("Fade was cancelled by a newer one.");
+                return Ok(false);
+            }
+            let volumes = ramp.volumes_at(step);
+            if volumes != last_volumes {
+                self.set_channel_volumes(&volumes)?;
+                self.notify_volume()?;
+                last_volumes = volumes;
+            }
+        }
+        Ok(true)
+    }
+
     pub fn cycle_through_interfaces(&mut self) -> Result<(), Error> {
-        self.sink_notification
-            .notify_start()
-            .unwrap_or_else(|e| error!("Failed to send the notification: {}", e));
-        match self.volume_control.cycle_through_interfaces() {
-            Err(e) => error!("Failed to change input: {}", e),
-            Ok(_) => {
-                let available_inputs = self.volume_control.get_available_interfaces();
-                match available_inputs {
-                    Ok(list) => self
-                        .sink_notification
-                        .notify(list)
-                        .unwrap_or_else(|e| error!("Failed to notify: {}", e)),
-                    Err(e) => error!("Failed to list available inputs: {}", e),
+        self.feedback.show_interface_change_start()?;
+        self.volume_control.cycle_through_interfaces()?;
+        self.show_interfaces()
+    }
+
+    /// Shows the interfaces after a switch and announces the active one.
+    fn show_interfaces(&mut self) -> Result<(), Error> {
+        let available_inputs = self.volume_control.get_available_interfaces()?.clone();
+        let now_playing = self.get_now_playing();
+        self.feedback
+            .show_interfaces(&available_inputs, now_playing.as_deref())?;
+        if !self.speak_active_interface() {
+            self.play_sound(SoundEvent::InputSwitched, false)?;
+        }
+        Ok(())
+    }
+
+    /// Switches the default sink or source, the port of the active sink, a card profile or the
+    /// sink of a stream to the one chosen by name or through a picker. Does nothing if the picker
+    /// was cancelled.
+    pub fn select_input(&mut self, target: SelectTarget, chooser: &Chooser) -> Result<(), Error> {
+        match target {
+            SelectTarget::Sink => {
+                let sinks = self.volume_control.get_available_interfaces()?.clone();
+                let choices = sink_choices(&sinks);
+                let chosen = match picker::choose(chooser, &choices)? {
+                    Some(chosen) => chosen,
+                    None => return Ok(()),
+                };
+                info!("Selecting sink {}.", sinks[chosen].sink_name);
+                self.volume_control.select_interface(sinks[chosen].index)?;
+                self.show_interfaces()
+            }
+            SelectTarget::Source => {
+                let sources: Vec<Source> = list_sources()?
+                    .into_iter()
+                    .filter(|s| s.monitor_of.is_none())
+                    .collect();
+                let choices: Vec<Choice> = sources
+                    .iter()
+                    .map(|s| Choice {
+                        name: s.source_name.clone(),
+                        label: s.name.clone(),
+                        active: s.active,
+                    })
+                    .collect();
+                let chosen = match picker::choose(chooser, &choices)? {
+                    Some(chosen) => chosen,
+                    None => return Ok(()),
+                };
+                info!("Selecting source {}.", sources[chosen].source_name);
+                self.volume_control
+                    .set_default_source(&sources[chosen].source_name)?;
+                self.show_selection("Audio Source", &choices[chosen])
+            }
+            SelectTarget::Port => {
+                self.volume_control.get_available_interfaces()?;
+                let sink = self
+                    .volume_control
+                    .active_interface
+                    .clone()
+                    .ok_or(Error::NoActiveSink)?;
+                let choices: Vec<Choice> = sink
+                    .ports
+                    .iter()
+                    .map(|p| Choice {
+                        name: p.name.clone(),
+                        label: if p.available {
+                            p.description.clone()
+                        } else {
+                            format!("{} (unplugged)", p.description)
+                        },
+                        active: sink.port.as_deref() == Some(p.name.as_str()),
+                    })
+                    .collect();
+                let chosen = match picker::choose(chooser, &choices)? {
+                    Some(chosen) => chosen,
+                    None => return Ok(()),
+                };
+                info!(
+                    "Selecting port {} of {}.",
+                    choices[chosen].name, sink.sink_name
+                );
+                self.volume_control
+                    .set_sink_port(&sink.sink_name, &choices[chosen].name)?;
+                self.show_selection(&sink.name, &choices[chosen])
+            }
+            SelectTarget::Profile => {
+                let mut cards = Vec::new();
+                let mut choices = Vec::new();
+                for card in list_cards()? {
+                    for profile in card.profiles.iter().filter(|p| p.available) {
+                        choices.push(Choice {
+                            name: profile.name.clone(),
+                            label: format!("{}: {}", card.description, profile.description),
+                            active: card.profile.as_deref() == Some(profile.name.as_str()),
+                        });
+                        cards.push(card.name.clone());
+                    }
                 }
+                let chosen = match picker::choose(chooser, &choices)? {
+                    Some(chosen) => chosen,
+                    None => return Ok(()),
+                };
+                info!(
+                    "Selecting profile {} of {}.",
+                    choices[chosen].name, cards[chosen]
+                );
+                self.volume_control
+                    .set_card_profile(&cards[chosen], &choices[chosen].name)?;
+                self.show_selection("Audio Profile", &choices[chosen])
             }
+            SelectTarget::Stream => {
+                let sinks = self.volume_control.get_available_interfaces()?.clone();
+                let sink_name = |index: i32| {
+                    sinks
+                        .iter()
+                        .find(|s| s.index == index)
+                        .map_or_else(|| index.to_string(), |s| s.name.clone())
+                };
+                let streams = list_sink_inputs()?;
+                let choices: Vec<Choice> = streams
+                    .iter()
+                    .map(|s| Choice {
+                        name: s.index.to_string(),
+                        label: format!("{} → {}", s.application, sink_name(s.sink)),
+                        active: false,
+                    })
+                    .collect();
+                let chosen = match picker::choose(chooser, &choices)? {
+                    Some(chosen) => chosen,
+                    None => return Ok(()),
+                };
+                let stream = &streams[chosen];
+                let sink_choices: Vec<Choice> = sinks
+                    .iter()
+                    .map(|s| Choice {
+                        name: s.sink_name.clone(),
+                        label: s.name.clone(),
+                        active: s.index == stream.sink,
+                    })
+                    .collect();
+                let sink = match picker::choose(chooser, &sink_choices)? {
+                    Some(sink) => sink,
+                    None => return Ok(()),
+                };
+                info!(
+                    "Moving stream {} to {}.",
+                    stream.index, sink_choices[sink].name
+                );
+                self.volume_control
+                    .move_sink_input(stream.index, &sink_choices[sink].name)?;
+                self.show_selection(&stream.application, &sink_choices[sink])
+            }
+        }
+    }
+
+    fn show_selection(&mut self, title: &str, choice: &Choice) -> Result<(), Error> {
+        self.feedback
+            .show_message(title, &format!("→ {}", choice.label))?;
+        self.play_sound(SoundEvent::InputSwitched, false)
+    }
+
+    /// Creates a sink that plays on all the given interfaces at once, or on all interfaces if none
+    /// are given, and switches to it. Combined interfaces are left out, as they would feed into
+    /// each other, and so are null sinks unless given.
+    pub fn combine_interfaces(&mut self, name: &str, interfaces: &[String]) -> Result<(), Error> {
+        const APPEAR_TIMEOUT: Duration = Duration::from_secs(2);
+        let sinks: Vec<Sink> = self
+            .volume_control
+            .get_available_interfaces()?
+            .iter()
+            .filter(|s| s.sink_name != name && !s.is_combined())
+            .cloned()
+            .collect();
+        let choices = sink_choices(&sinks);
+        let mut combined: Vec<Sink> = Vec::new();
+        for interface in interfaces {
+            if let Some(index) = picker::choose(&Chooser::Name(interface), &choices)? {
+                if !combined.contains(&sinks[index]) {
+                    combined.push(sinks[index].clone());
+                }
+            }
+        }
+        if interfaces.is_empty() {
+            combined = sinks.into_iter().filter(|s| !s.is_null()).collect();
+        }
+        if combined.len() < 2 {
+            return Err(Error::Unavailable(String::from(
+                "Combining needs at least two interfaces.",
+            )));
+        }
+        self.volume_control.create_combined_sink(name, &combined)?;
+
+        // pipewire-pulse adds the sink a moment after loading the module.
+        let deadline = Instant::now() + APPEAR_TIMEOUT;
+        loop {
+            let combined_sink = self
+                .volume_control
+                .get_available_interfaces()?
+                .iter()
+                .find(|s| s.sink_name == name)
+                .map(|s| s.index);
+            if let Some(index) = combined_sink {
+                self.volume_control.select_interface(index)?;
+                return self.show_interfaces();
+            }
+            if Instant::now() > deadline {
+                return Err(Error::Unavailable(format!(
+                    "The combined interface {} did not appear.",
+                    name
+                )));
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    /// Removes a combined sink. The sound server moves its streams to another interface.
+    pub fn uncombine_interfaces(&mut self, name: &str) -> Result<(), Error> {
+        if !self.volume_control.remove_combined_sink(name)? {
+            return Err(Error::Unavailable(format!(
+                "There is no combined interface {}.",
+                name
+            )));
+        }
+        self.show_interfaces()
+    }
+
+    pub fn toggle_microphone_mute(&mut self) -> Result<(), Error> {
+        let source = get_default_source()?;
+        self.volume_control.toggle_source_mute(&source)?;
+        let muted = is_source_muted(&source)?;
+        info!(
+            "Microphone {} is {}.",
+            source,
+            if muted { "muted" } else { "live" }
+        );
+        self.feedback.show_microphone(&source, !muted)?;
+        Ok(())
+    }
+
+    /// Unmutes the default source until `ptt stop` is run or the process is stopped.
+    pub fn push_to_talk(&mut self) -> Result<(), Error> {
+        ptt::recover()?;
+        let source = get_default_source()?;
+        let live_source = match LiveSource::unmute(&self.volume_control, &source)? {
+            Some(live_source) => live_source,
+            None => return Ok(()),
         };
-        self.play_sound(false)?;
+        self.feedback.show_microphone(&source, true)?;
+        let signal = live_source.wait_for_release();
+        drop(live_source);
+        self.feedback.show_microphone(&source, false)?;
+        if let Some(signal) = signal {
+            ptt::forward_signal(signal);
+        }
+        Ok(())
+    }
+
+    pub fn stop_push_to_talk(&mut self) -> Result<(), Error> {
+        ptt::release(&self.volume_control)
+    }
+
+    pub fn save_scene(&mut self, name: &str) -> Result<(), Error> {
+        let scene = Scene::capture(&mut self.volume_control)?;
+        scene.save(name)?;
+        self.feedback.show_message(
+            &format!("Saved scene \"{}\"", name),
+            &format!(
+                "{} sinks, {} streams",
+                scene.sinks.len(),
+                scene.streams.len()
+            ),
+        )?;
+        Ok(())
+    }
+
+    /// Restores the routing and the volumes of a saved scene and shows a summary.
+    pub fn load_scene(&mut self, name: &str) -> Result<(), Error> {
+        let scene = Scene::load(name)?;
+        info!("Loading scene \"{}\".", name);
+        let max_volume = self.volume_config.get_current_max_volume();
+        let summary = scene.apply(&mut self.volume_control, max_volume)?;
+        let mut body = Vec::new();
+        if let Some(default_sink) = &summary.default_sink {
+            body.push(format!("→ {}", default_sink));
+        }
+        body.push(format!(
+            "{} of {} sinks restored, {} streams moved",
+            summary.sinks,
+            scene.sinks.len(),
+            summary.streams
+        ));
+        if summary.failures > 0 {
+            body.push(format!("{} settings failed", summary.failures));
+        }
+        self.feedback
+            .show_message(&format!("Scene \"{}\"", name), &body.join("\n"))?;
+        self.play_sound(SoundEvent::InputSwitched, false)?;
         Ok(())
     }
+
+    /// Shows the live level of a source for the given duration. Defaults to the monitor of the
+    /// active interface.
+    pub fn show_meter(&mut self, source: Option<&str>, duration: Duration) -> Result<(), Error> {
+        let source = match source {
+            Some("mic") => String::from("@DEFAULT_SOURCE@"),
+            Some(source) => source.to_string(),
+            None => match &self.volume_control.active_interface {
+                Some(active_interface) => format!("{}.monitor", active_interface.sink_name),
+                None => return Err(Error::NoActiveSink),
+            },
+        };
+        info!("Metering {} for {:?}.", source, duration);
+        let meter = PeakMeter::new(&source)?;
+        let deadline = Instant::now() + duration;
+        while Instant::now() < deadline {
+            let level = PeakMeter::peak_to_level(meter.read_peak(Duration::from_millis(200)));
+            self.feedback.show_level(&source, level)?;
+        }
+        self.feedback.finish_level()
+    }
+
+    /// Opens the full-screen terminal mixer until it is quit.
+    pub fn run_mixer(&mut self) -> Result<(), Error> {
+        let step = self.volume_config.get_step().round().max(1.0) as i32;
+        tui::run(&mut self.volume_control, &self.volume_config, step)
+    }
+
+    /// Returns whether the name of the active interface was spoken.
+    fn speak_active_interface(&self) -> bool {
+        if let (Some(speaker), Some(active_interface)) =
+            (&self.speaker, &self.volume_control.active_interface)
+        {
+            match speaker.speak(&active_interface.name, active_interface.index) {
+                Ok(()) => return true,
+                Err(e) => error!("Failed to speak the interface name: {}", e),
+            }
+        }
+        false
+    }
 }
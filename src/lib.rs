@@ -0,0 +1,77 @@
+//! Change the volume, switch between audio interfaces and show what happened.
+//!
+//! [`volume_control::VolumeControl`] talks to the sound server and lists its sinks, sources and
+//! streams as plain structs. [`volume::VolumeInfo`] reads the volume of the default sink.
+//! [`interface::Interface`] ties both to the configured feedback and sounds, like the
+//! `audio_tools` binary does. With the default `async` feature, [`asynchronous`] offers the same
+//! without blocking, along with streams of the changes on the sound server.
+//!
+//! The structs and enums are `#[non_exhaustive]`, so that new fields and variants are not
+//! breaking changes. They are read, not built, except through their constructors and
+//! `Config::default()`.
+//!
+//! ```no_run
+//! use audio_tools::config::Config;
+//! use audio_tools::interface::Interface;
+//! use audio_tools::volume_control::{list_sink_inputs, VolumeControl};
+//!
+//! # fn main() -> Result<(), audio_tools::Error> {
+//! let mut volume_control = VolumeControl::new()?;
+//! for sink in volume_control.get_available_interfaces()? {
+//!     println!("{} ({}%)", sink.name, sink.volumes.iter().max().unwrap_or(&0));
+//! }
+//! println!("{} streams", list_sink_inputs()?.len());
+//!
+//! let mut interface = Interface::new(&Config::load(), None, None)?;
+//! interface.step_volume(1)?;
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod config;
+pub mod error;
+pub mod interface;
+pub mod mpris;
+pub mod picker;
+pub mod volume;
+pub mod volume_control;
+
+mod command;
+mod daemon;
+mod ducking;
+mod fade;
+mod feedback;
+mod hotkeys;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod network;
+mod notification;
+mod ptt;
+mod scene;
+mod schedule;
+mod sound;
+mod sound_theme;
+mod speech;
+mod state;
+mod tray;
+mod tui;
+
+use log::error;
+
+pub use crate::error::Error;
+
+use crate::command::{execute_command, parse_command, CommandType};
+use crate::config::Config;
+
+/// Runs a command line without the program name, e.g. `["fade", "20"]`, like the binary does.
+/// Starts the daemon for "daemon".
+pub fn run(config: Config, args: &[String]) -> Result<(), Error> {
+    let command = parse_command(args)?;
+    ptt::recover().unwrap_or_else(|e| error!("Failed to recover push-to-talk: {}", e));
+    if command == CommandType::Daemon {
+        return daemon::run(config);
+    }
+    execute_command(&config, command)
+}
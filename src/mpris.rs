@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+
+use dbus::arg::{RefArg, Variant};
+use dbus::stdintf::org_freedesktop_dbus::Properties;
+use dbus::{BusType, Connection, Message};
+use log::{debug, error, info};
+
+use crate::error::Error;
+use crate::volume_control::SinkInput;
+
+const PLAYER_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const PLAYER_PATH: &str = "/org/mpris/MediaPlayer2";
+const ROOT_INTERFACE: &str = "org.mpris.MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+const TIMEOUT_MS: i32 = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum PlayerAction {
+    PlayPause,
+    Next,
+    Previous,
+}
+
+impl PlayerAction {
+    fn method(self) -> &'static str {
+        match self {
+            PlayerAction::PlayPause => "PlayPause",
+            PlayerAction::Next => "Next",
+            PlayerAction::Previous => "Previous",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Player {
+    pub bus_name: String,
+    /// Name of the player, e.g. "Spotify".
+    pub identity: String,
+    /// "Playing", "Paused" or "Stopped".
+    pub status: String,
+    /// "Artist - Title", if the player tells.
+    pub track: Option<String>,
+    pub process_id: Option<u32>,
+}
+
+impl Player {
+    pub fn is_playing(&self) -> bool {
+        self.status == "Playing"
+    }
+
+    /// Whether the stream was opened by this player.
+    pub fn owns(&self, sink_input: &SinkInput) -> bool {
+        let application = sink_input.application.to_lowercase();
+        (self.process_id.is_some() && self.process_id == sink_input.process_id)
+            || (!application.is_empty()
+                && (application == self.identity.to_lowercase()
+                    || self.bus_name.to_lowercase().contains(&application)))
+    }
+
+    pub fn describe(&self) -> String {
+        match &self.track {
+            Some(track) => format!("{}: {}", self.identity, track),
+            None => self.identity.clone(),
+        }
+    }
+}
+
+fn build_track(metadata: &HashMap<String, Variant<Box<dyn RefArg>>>) -> Option<String> {
+    let title = metadata.get("xesam:title").and_then(|t| t.0.as_str());
+    let artists = metadata.get("xesam:artist").and_then(|a| {
+        a.0.as_iter().map(|artists| {
+            artists
+                .filter_map(|artist| artist.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        })
+    });
+    match (artists, title) {
+        (Some(artists), Some(title)) if !artists.is_empty() => {
+            Some(format!("{} - {}", artists, title))
+        }
+        (_, Some(title)) if !title.is_empty() => Some(title.to_string()),
+        _ => None,
+    }
+}
+
+/// Media players on the session bus.
+pub struct Mpris {
+    connection: Connection,
+}
+
+impl Mpris {
+    pub fn new() -> Result<Self, Error> {
+        Ok(Mpris {
+            connection: Connection::get_private(BusType::Session)?,
+        })
+    }
+
+    fn call_bus(&self, method: &str, argument: Option<&str>) -> Result<Message, Error> {
+        let message = Message::new_method_call(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            method,
+        )
+        .map_err(Error::Dbus)?;
+        let message = match argument {
+            Some(argument) => message.append1(argument),
+            None => message,
+        };
+        Ok(self
+            .connection
+            .send_with_reply_and_block(message, TIMEOUT_MS)?)
+    }
+
+    fn get_player(&self, bus_name: &str) -> Result<Player, Error> {
+        let path = self.connection.with_path(bus_name, PLAYER_PATH, TIMEOUT_MS);
+        let identity = path
+            .get::<String>(ROOT_INTERFACE, "Identity")
+            .unwrap_or_else(|_| bus_name.trim_start_matches(PLAYER_PREFIX).to_string());
+        let status = path.get::<String>(PLAYER_INTERFACE, "PlaybackStatus")?;
+        let track = path
+            .get::<HashMap<String, Variant<Box<dyn RefArg>>>>(PLAYER_INTERFACE, "Metadata")
+            .ok()
+            .and_then(|metadata| build_track(&metadata));
+        let process_id = self
+            .call_bus("GetConnectionUnixProcessID", Some(bus_name))
+            .ok()
+            .and_then(|reply| reply.get1::<u32>());
+        Ok(Player {
+            bus_name: bus_name.to_string(),
+            identity,
+            status,
+            track,
+            process_id,
+        })
+    }
+
+    pub fn list_players(&self) -> Result<Vec<Player>, Error> {
+        let names: Vec<String> = self.call_bus("ListNames", None)?.read1()?;
+        Ok(names
+            .iter()
+            .filter(|name| name.starts_with(PLAYER_PREFIX))
+            .filter_map(|name| match self.get_player(name) {
+                Ok(player) => Some(player),
+                Err(e) => {
+                    error!("Failed to query player {}: {}", name, e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    pub fn call_player(&self, bus_name: &str, method: &str) -> Result<(), Error> {
+        debug!("Calling {} on {}.", method, bus_name);
+        let message = Message::new_method_call(bus_name, PLAYER_PATH, PLAYER_INTERFACE, method)
+            .map_err(Error::Dbus)?;
+        self.connection
+            .send_with_reply_and_block(message, TIMEOUT_MS)?;
+        Ok(())
+    }
+
+    /// Controls the playing player, or the first one if none is playing.
+    pub fn control(&self, action: PlayerAction) -> Result<(), Error> {
+        let players = self.list_players()?;
+        let player = players
+            .iter()
+            .find(|p| p.is_playing())
+            .or_else(|| players.first())
+            .ok_or_else(|| Error::Unavailable(String::from("No media player is running.")))?;
+        info!("{:?} on {}.", action, player.identity);
+        self.call_player(&player.bus_name, action.method())
+    }
+
+    /// Pauses all playing players. Returns their bus names to resume them later.
+    pub fn pause_playing(&self) -> Result<Vec<String>, Error> {
+        let mut paused = Vec::new();
+        for player in self.list_players()?.iter().filter(|p| p.is_playing()) {
+            info!("Pausing {}.", player.identity);
+            match self.call_player(&player.bus_name, "Pause") {
+                Ok(()) => paused.push(player.bus_name.clone()),
+                Err(e) => error!("Failed to pause {}: {}", player.identity, e),
+            }
+        }
+        Ok(paused)
+    }
+
+    pub fn resume(&self, bus_names: &[String]) {
+        for bus_name in bus_names {
+            info!("Resuming {}.", bus_name);
+            self.call_player(bus_name, "Play")
+                .unwrap_or_else(|e| error!("Failed to resume {}: {}", bus_name, e));
+        }
+    }
+
+    /// The playing player with a stream on the given sink.
+    pub fn now_playing(
+        &self,
+        sink_index: i32,
+        sink_inputs: &[SinkInput],
+    ) -> Result<Option<Player>, Error> {
+        let sink_inputs: Vec<&SinkInput> = sink_inputs
+            .iter()
+            .filter(|i| i.sink == sink_index && !i.corked)
+            .collect();
+        Ok(self
+            .list_players()?
+            .into_iter()
+            .find(|p| p.is_playing() && sink_inputs.iter().any(|i| p.owns(i))))
+    }
+}
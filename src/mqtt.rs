@@ -0,0 +1,322 @@
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use log::{debug, error, info};
+use rumqttc::{Client, Event, LastWill, MqttOptions, Packet, QoS};
+use serde_json::json;
+
+use crate::command::{execute_command, parse_volume, CommandType, SelectTarget};
+use crate::config::{Config, MqttConfig};
+use crate::error::Error;
+use crate::volume::VolumeInfo;
+use crate::volume_control::{follow_changes, Facility, VolumeControl};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+/// What is published, each value on its own topic.
+#[derive(Clone, Debug, PartialEq)]
+struct State {
+    volume: i32,
+    muted: bool,
+    /// Description of the active sink.
+    sink: Option<String>,
+    /// Descriptions of all sinks, the options of the select in Home Assistant.
+    sinks: Vec<String>,
+}
+
+impl State {
+    fn read(volume_control: &mut VolumeControl) -> Result<Self, Error> {
+        let volume = VolumeInfo::get_volume()?;
+        let sinks = volume_control.get_available_interfaces()?;
+        Ok(State {
+            volume: volume.volume,
+            muted: volume.muted,
+            sink: sinks.iter().find(|s| s.active).map(|s| s.name.clone()),
+            sinks: sinks.iter().map(|s| s.name.clone()).collect(),
+        })
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "ON"
+    } else {
+        "OFF"
+    }
+}
+
+/// Publishes the state and the Home Assistant discovery payloads, each only when it changed.
+struct Publisher {
+    client: Client,
+    config: MqttConfig,
+    volume_control: VolumeControl,
+    last_state: Option<State>,
+}
+
+impl Publisher {
+    fn topic(&self, name: &str) -> String {
+        format!("{}/{}", self.config.topic, name)
+    }
+
+    fn publish(&self, topic: String, payload: String) -> Result<(), Error> {
+        debug!("Publishing \"{}\" to {}.", payload, topic);
+        self.client
+            .publish(topic, QoS::AtLeastOnce, true, payload)
+            .map_err(|e| Error::Unavailable(format!("MQTT: {}", e)))
+    }
+
+    /// Lets Home Assistant create a number for the volume, a switch for mute, a select for the
+    /// sink and buttons to raise and lower the volume.
+    fn publish_discovery(&self, state: &State) -> Result<(), Error> {
+        let node = self.config.topic.replace(['/', ' '], "_");
+        let hostname = fs::read_to_string("/proc/sys/kernel/hostname").unwrap_or_default();
+        let device = json!({
+            "identifiers": [node],
+            "name": format!("audio_tools {}", hostname.trim()).trim_end(),
+            "model": "audio_tools",
+        });
+        let entities = [
+            (
+                "number",
+                "volume",
+                json!({
+                    "name": "Volume",
+                    "state_topic": self.topic("volume"),
+                    "command_topic": self.topic("set"),
+                    "min": 0,
+                    "max": 100,
+                    "unit_of_measurement": "%",
+                    "icon": "mdi:volume-high",
+                }),
+            ),
+            (
+                "switch",
+                "mute",
+                json!({
+                    "name": "Mute",
+                    "state_topic": self.topic("muted"),
+                    "command_topic": self.topic("mute"),
+                    "icon": "mdi:volume-off",
+                }),
+            ),
+            (
+                "select",
+                "sink",
+                json!({
+                    "name": "Output",
+                    "state_topic": self.topic("sink"),
+                    "command_topic": self.topic("select_input"),
+                    "options": state.sinks,
+                    "icon": "mdi:speaker",
+                }),
+            ),
+            (
+                "button",
+                "raise",
+                json!({
+                    "name": "Raise volume",
+                    "command_topic": self.topic("raise"),
+                    "icon": "mdi:volume-plus",
+                }),
+            ),
+            (
+                "button",
+                "lower",
+                json!({
+                    "name": "Lower volume",
+                    "command_topic": self.topic("lower"),
+                    "icon": "mdi:volume-minus",
+                }),
+            ),
+        ];
+        for (component, object, mut payload) in entities {
+            payload["unique_id"] = json!(format!("{}_{}", node, object));
+            payload["availability_topic"] = json!(self.topic("available"));
+            payload["device"] = device.clone();
+            let topic = format!(
+                "{}/{}/{}/{}/config",
+                self.config.discovery_prefix, component, node, object
+            );
+            self.publish(topic, payload.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Publishes what changed since the last time, or everything after connecting.
+    fn update(&mut self, connected: bool) -> Result<(), Error> {
+        let state = State::read(&mut self.volume_control)?;
+        let last_state = if connected {
+            self.publish(self.topic("available"), String::from("online"))?;
+            None
+        } else {
+            self.last_state.take()
+        };
+        if last_state.as_ref() == Some(&state) {
+            self.last_state = last_state;
+            return Ok(());
+        }
+        let last = last_state.as_ref();
+        if last.is_none_or(|s| s.volume != state.volume) {
+            self.publish(self.topic("volume"), state.volume.to_string())?;
+        }
+        if last.is_none_or(|s| s.muted != state.muted) {
+            self.publish(self.topic("muted"), on_off(state.muted).to_string())?;
+        }
+        if last.is_none_or(|s| s.sink != state.sink) {
+            self.publish(self.topic("sink"), state.sink.clone().unwrap_or_default())?;
+        }
+        if self.config.discovery && last.is_none_or(|s| s.sinks != state.sinks) {
+            self.publish_discovery(&state)?;
+        }
+        self.last_state = Some(state);
+        Ok(())
+    }
+}
+
+/// Turns a message on a command topic into a command. None if there is nothing to do, e.g. when
+/// muting while muted.
+fn parse_command(
+    config: &MqttConfig,
+    topic: &str,
+    payload: &str,
+) -> Result<Option<CommandType>, Error> {
+    let name = topic
+        .strip_prefix(&config.topic)
+        .and_then(|t| t.strip_prefix('/'))
+        .unwrap_or(topic);
+    let command = match name {
+        "set" => CommandType::Fade {
+            target: parse_volume(payload, 100)?,
+            duration: Duration::ZERO,
+        },
+        "raise" => CommandType::VolumeRaise,
+        "lower" => CommandType::VolumeLower,
+        "mute" => {
+            let muted = VolumeInfo::get_volume()?.muted;
+            match payload.to_uppercase().as_str() {
+                "ON" | "1" | "TRUE" if muted => return Ok(None),
+                "OFF" | "0" | "FALSE" if !muted => return Ok(None),
+                _ => CommandType::VolumeToggleMute,
+            }
+        }
+        "select_input" => CommandType::Select {
+            target: SelectTarget::Sink,
+            picker: None,
+            name: Some(payload.to_string()),
+        },
+        _ => {
+            return Err(Error::Usage(format!(
+                "Unknown command topic \"{}\".",
+                topic
+            )))
+        }
+    };
+    Ok(Some(command))
+}
+
+/// Publishes the volume, mute status and active sink and runs the commands sent to the command
+/// topics, reconnecting whenever the broker goes away.
+pub fn run(config: Arc<Config>) {
+    let mqtt_config = config.mqtt.clone();
+    let mut options = MqttOptions::new(
+        mqtt_config.client_id.clone(),
+        mqtt_config.host.clone(),
+        mqtt_config.port,
+    );
+    options.set_keep_alive(KEEP_ALIVE);
+    options.set_last_will(LastWill::new(
+        format!("{}/available", mqtt_config.topic),
+        "offline",
+        QoS::AtLeastOnce,
+        true,
+    ));
+    if !mqtt_config.username.is_empty() {
+        options.set_credentials(mqtt_config.username.clone(), mqtt_config.password.clone());
+    }
+    let (client, mut connection) = Client::new(options, 16);
+    let publisher = match VolumeControl::new() {
+        Ok(volume_control) => Arc::new(Mutex::new(Publisher {
+            client: client.clone(),
+            config: mqtt_config.clone(),
+            volume_control,
+            last_state: None,
+        })),
+        Err(e) => {
+            error!("Failed to start MQTT: {}", e);
+            return;
+        }
+    };
+    info!(
+        "Connecting to the MQTT broker at {}:{}.",
+        mqtt_config.host, mqtt_config.port
+    );
+
+    let change_publisher = publisher.clone();
+    thread::spawn(move || {
+        let changes = follow_changes(&[Facility::Sink, Facility::Server]);
+        while changes.recv().is_ok() {
+            // A single change often comes as several events.
+            while changes.try_recv().is_ok() {}
+            change_publisher
+                .lock()
+                .unwrap()
+                .update(false)
+                .unwrap_or_else(|e| error!("Failed to publish the state: {}", e));
+        }
+    });
+
+    let command_topics = ["set", "raise", "lower", "mute", "select_input"];
+    for notification in connection.iter() {
+        match notification {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                info!("Connected to the MQTT broker.");
+                // Publishing blocks until the connection sends, so not on this thread.
+                let client = client.clone();
+                let publisher = publisher.clone();
+                let topics: Vec<String> = command_topics
+                    .iter()
+                    .map(|t| format!("{}/{}", mqtt_config.topic, t))
+                    .collect();
+                thread::spawn(move || {
+                    for topic in topics {
+                        client
+                            .subscribe(topic, QoS::AtLeastOnce)
+                            .unwrap_or_else(|e| error!("Failed to subscribe: {}", e));
+                    }
+                    publisher
+                        .lock()
+                        .unwrap()
+                        .update(true)
+                        .unwrap_or_else(|e| error!("Failed to publish the state: {}", e));
+                });
+            }
+            Ok(Event::Incoming(Packet::Publish(message))) => {
+                let payload = String::from_utf8_lossy(&message.payload).trim().to_string();
+                info!("MQTT command on {}: \"{}\".", message.topic, payload);
+                let config = config.clone();
+                thread::spawn(move || {
+                    parse_command(&config.mqtt, &message.topic, &payload)
+                        .and_then(|command| match command {
+                            Some(command) if !command.is_remote() => Err(Error::Usage(format!(
+                                "{} cannot be run over MQTT.",
+                                message.topic
+                            ))),
+                            Some(command) => execute_command(&config, command),
+                            None => Ok(()),
+                        })
+                        .unwrap_or_else(|e| {
+                            error!("MQTT command on {} failed: {}", message.topic, e)
+                        });
+                });
+            }
+            Ok(_) => {}
+            Err(e) => {
+                error!("MQTT connection failed, reconnecting: {}", e);
+                thread::sleep(RECONNECT_DELAY);
+            }
+        }
+    }
+}
@@ -0,0 +1,390 @@
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::daemon;
+use crate::error::Error;
+use crate::volume::VolumeInfo;
+use crate::volume_control::{follow_changes, Facility, VolumeControl};
+
+const MAX_HEADER_LINES: usize = 64;
+const MAX_LINE_LENGTH: u64 = 8 * 1024;
+const MAX_BODY_LENGTH: usize = 64 * 1024;
+/// Clients served at once, each on its own thread. Others are turned away until one is done.
+const MAX_CLIENTS: usize = 32;
+/// Idle clients are dropped after this.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// Comments are sent this often on the event stream to notice clients that are gone.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+struct SinkStatus {
+    name: String,
+    description: String,
+    active: bool,
+    volume: i32,
+    muted: bool,
+}
+
+/// The volume of the default sink and all sinks, as served on /status and /events.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+struct Status {
+    volume: i32,
+    muted: bool,
+    /// Name of the default sink.
+    sink: Option<String>,
+    sinks: Vec<SinkStatus>,
+}
+
+impl Status {
+    fn read(volume_control: &mut VolumeControl) -> Result<Self, Error> {
+        let volume = VolumeInfo::get_volume()?;
+        let sinks: Vec<SinkStatus> = volume_control
+            .get_available_interfaces()?
+            .iter()
+            .map(|s| SinkStatus {
+                name: s.sink_name.clone(),
+                description: s.name.clone(),
+                active: s.active,
+                volume: s.volumes.iter().copied().max().unwrap_or(0),
+                muted: s.muted,
+            })
+            .collect();
+        Ok(Status {
+            volume: volume.volume,
+            muted: volume.muted,
+            sink: sinks.iter().find(|s| s.active).map(|s| s.name.clone()),
+            sinks,
+        })
+    }
+
+    fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string(self).map_err(|e| Error::parse("", e))
+    }
+}
+
+#[derive(Deserialize)]
+struct CommandRequest {
+    /// A command line like on the socket of the daemon, e.g. "fade 20 --duration 5000".
+    command: String,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    /// From the Authorization header or the "token" query parameter, which browsers need for
+    /// EventSource.
+    token: Option<String>,
+    body: Vec<u8>,
+}
+
+/// Counts a client as connected until dropped.
+struct ClientSlot(Arc<AtomicUsize>);
+
+impl ClientSlot {
+    /// None if there are too many clients already.
+    fn take(clients: &Arc<AtomicUsize>) -> Option<Self> {
+        clients
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                (count < MAX_CLIENTS).then_some(count + 1)
+            })
+            .ok()
+            .map(|_| ClientSlot(clients.clone()))
+    }
+}
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Clients of /events, each sent the status as JSON whenever it changed.
+type Subscribers = Arc<Mutex<Vec<Sender<String>>>>;
+
+fn read_line(reader: &mut BufReader<TcpStream>) -> Result<String, Error> {
+    let mut line = String::new();
+    reader.by_ref().take(MAX_LINE_LENGTH).read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        return Err(Error::parse(line, "Line too long or incomplete."));
+    }
+    Ok(line.trim_end().to_string())
+}
+
+/// Decodes a query parameter, e.g. from JavaScript's `encodeURIComponent`. None if it is not
+/// valid.
+fn percent_decode(value: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut rest = value.bytes();
+    while let Some(byte) = rest.next() {
+        bytes.push(match byte {
+            b'%' => {
+                let hex = [rest.next()?, rest.next()?];
+                u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?
+            }
+            b'+' => b' ',
+            byte => byte,
+        });
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn read_request(stream: &TcpStream) -> Result<Request, Error> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let request_line = read_line(&mut reader)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target),
+        _ => return Err(Error::parse(&request_line, "Not an HTTP request.")),
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut token = query
+        .split('&')
+        .find_map(|p| p.strip_prefix("token="))
+        .and_then(percent_decode);
+
+    let mut content_length = 0;
+    for _ in 0..MAX_HEADER_LINES {
+        let line = read_line(&mut reader)?;
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.trim().to_lowercase(), value.trim()),
+            None => return Err(Error::parse(&line, "Invalid header.")),
+        };
+        match name.as_str() {
+            "content-length" => {
+                content_length = value.parse().map_err(|e| Error::parse(&line, e))?;
+            }
+            "authorization" => {
+                token = value.strip_prefix("Bearer ").map(|t| t.trim().to_string());
+            }
+            _ => {}
+        }
+    }
+    if content_length > MAX_BODY_LENGTH {
+        return Err(Error::parse(
+            content_length.to_string(),
+            "Request body too large.",
+        ));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Request {
+        method,
+        path: path.to_string(),
+        token,
+        body,
+    })
+}
+
+/// Compares in constant time, so that the time taken does not give the token away.
+fn token_matches(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+fn respond(mut stream: &TcpStream, status: &str, body: &str) -> Result<(), Error> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+fn respond_error(stream: &TcpStream, status: &str, message: &str) -> Result<(), Error> {
+    let body = serde_json::json!({ "error": message }).to_string();
+    respond(stream, status, &body)
+}
+
+fn run_command(config: &Config, stream: &TcpStream, body: &[u8]) -> Result<(), Error> {
+    let request: CommandRequest = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => return respond_error(stream, "400 Bad Request", &e.to_string()),
+    };
+    info!("Network client sent \"{}\".", request.command);
+    match daemon::execute_remote(config, &request.command) {
+        Ok(()) => respond(stream, "200 OK", r#"{"ok":true}"#),
+        Err(e) => {
+            error!("Failed to run \"{}\": {}", request.command, e);
+            let status = match e {
+                Error::Usage(_) | Error::ParseError { .. } => "400 Bad Request",
+                _ => "500 Internal Server Error",
+            };
+            respond_error(stream, status, &e.to_string())
+        }
+    }
+}
+
+/// Sends the status as server-sent events, right away and whenever it changed, until the client
+/// is gone.
+fn stream_events(mut stream: &TcpStream, subscribers: &Subscribers) -> Result<(), Error> {
+    let status = Status::read(&mut VolumeControl::new()?)?;
+    let (sender, receiver) = mpsc::channel();
+    subscribers.lock().unwrap().push(sender);
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\r\n"
+    )?;
+    write!(stream, "data: {}\n\n", status.to_json()?)?;
+    loop {
+        match receiver.recv_timeout(KEEPALIVE_INTERVAL) {
+            Ok(status) => write!(stream, "data: {}\n\n", status)?,
+            Err(RecvTimeoutError::Timeout) => write!(stream, ": keepalive\n\n")?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
+
+fn handle_client(
+    config: &Config,
+    stream: TcpStream,
+    subscribers: &Subscribers,
+) -> Result<(), Error> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let request = match read_request(&stream) {
+        Ok(request) => request,
+        Err(e) => return respond_error(&stream, "400 Bad Request", &e.to_string()),
+    };
+    debug!(
+        "Network client requested {} {}.",
+        request.method, request.path
+    );
+    let authorized = request
+        .token
+        .as_deref()
+        .is_some_and(|t| token_matches(t, &config.network.token));
+    if !authorized {
+        warn!("Rejected a network client without a valid token.");
+        return respond_error(&stream, "401 Unauthorized", "Invalid token.");
+    }
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/status") => match Status::read(&mut VolumeControl::new()?) {
+            Ok(status) => respond(&stream, "200 OK", &status.to_json()?),
+            Err(e) => respond_error(&stream, "500 Internal Server Error", &e.to_string()),
+        },
+        ("POST", "/command") => run_command(config, &stream, &request.body),
+        ("GET", "/events") => stream_events(&stream, subscribers),
+        (_, "/status") | (_, "/command") | (_, "/events") => {
+            respond_error(&stream, "405 Method Not Allowed", "Method not allowed.")
+        }
+        _ => respond_error(&stream, "404 Not Found", "Not found."),
+    }
+}
+
+/// Sends the status to the clients of /events whenever it changed.
+fn push_changes(subscribers: Subscribers) -> Result<(), Error> {
+    let mut volume_control = VolumeControl::new()?;
+    // Clients get the current status when they connect, so only changes are pushed.
+    let mut last_status = Status::read(&mut volume_control).ok();
+    let changes = follow_changes(&[Facility::Sink, Facility::Server]);
+    while changes.recv().is_ok() {
+        // A single change often comes as several events.
+        while changes.try_recv().is_ok() {}
+        let status = match Status::read(&mut volume_control) {
+            Ok(status) => status,
+            Err(e) => {
+                error!("Failed to read the status: {}", e);
+                continue;
+            }
+        };
+        if last_status.as_ref() == Some(&status) {
+            continue;
+        }
+        let json = status.to_json()?;
+        subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(json.clone()).is_ok());
+        last_status = Some(status);
+    }
+    Ok(())
+}
+
+/// Accepts the commands of the socket of the daemon that only change the volume or interface over
+/// HTTP, e.g. from phones or other computers, along with the status and a stream of its changes.
+/// Every request needs the token of the config.
+pub fn run(config: Arc<Config>) {
+    if config.network.token.is_empty() {
+        error!("The network listener needs a token in the config.");
+        return;
+    }
+    let listener = match TcpListener::bind(&config.network.address) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Failed to listen on {}: {}", config.network.address, e);
+            return;
+        }
+    };
+    info!(
+        "Listening for network clients on {}.",
+        config.network.address
+    );
+
+    let subscribers: Subscribers = Arc::new(Mutex::new(Vec::new()));
+    let push_subscribers = subscribers.clone();
+    thread::spawn(move || {
+        push_changes(push_subscribers)
+            .unwrap_or_else(|e| error!("Stopped pushing status changes: {}", e));
+    });
+    let clients = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let slot = match ClientSlot::take(&clients) {
+                    Some(slot) => slot,
+                    None => {
+                        warn!("Too many network clients, turning one away.");
+                        respond_error(&stream, "503 Service Unavailable", "Too many clients.")
+                            .unwrap_or_else(|e| debug!("Network client failed: {}", e));
+                        continue;
+                    }
+                };
+                let config = config.clone();
+                let subscribers = subscribers.clone();
+                thread::spawn(move || {
+                    let _slot = slot;
+                    handle_client(&config, stream, &subscribers)
+                        .unwrap_or_else(|e| debug!("Network client failed: {}", e));
+                });
+            }
+            Err(e) => error!("Failed to accept network client: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_query_tokens() {
+        assert_eq!(percent_decode("abc123").as_deref(), Some("abc123"));
+        assert_eq!(
+            percent_decode("a%2Bb%26c%3Dd%25e").as_deref(),
+            Some("a+b&c=d%e")
+        );
+        assert_eq!(percent_decode("a+b").as_deref(), Some("a b"));
+        assert_eq!(percent_decode("%e2%86%92").as_deref(), Some("→"));
+        assert_eq!(percent_decode("a%2"), None);
+        assert_eq!(percent_decode("a%zz"), None);
+        assert_eq!(percent_decode("%ff"), None);
+    }
+}
@@ -1,12 +1,17 @@
-use std::process;
+use std::path::PathBuf;
 use std::str;
+use std::thread;
+use std::time::Duration;
 
-use failure::{bail, Error};
 use log::{debug, error};
-use notify_rust::Notification;
+use notify_rust::{Notification, Timeout};
 
+use crate::config::SoundConfig;
+use crate::error::Error;
+use crate::sound::{self, PeakMeter, Sound};
+use crate::sound_theme::{SoundTheme, ThemeSound};
 use crate::volume::VolumeInfo;
-use crate::volume_control::Interface;
+use crate::volume_control::{list_sink_inputs, Sink};
 
 struct NotificationWrapper {
     id: Option<u32>,
@@ -16,26 +21,38 @@ struct NotificationWrapper {
 impl NotificationWrapper {
     fn new(id: Option<u32>, default_summary: String) -> NotificationWrapper {
         NotificationWrapper {
-            id: id,
-            default_summary: default_summary,
+            id,
+            default_summary,
         }
     }
 
     fn notify(&mut self, summary: Option<&str>, body: &str) -> Result<(), Error> {
-        let mut notification = Notification::new().icon("audio-headphones").finalize();
+        self.notify_with_timeout(summary, body, Timeout::Default)
+    }
+
+    fn notify_with_timeout(
+        &mut self,
+        summary: Option<&str>,
+        body: &str,
+        timeout: Timeout,
+    ) -> Result<(), Error> {
+        let mut notification = Notification::new()
+            .icon("audio-headphones")
+            .timeout(timeout)
+            .finalize();
 
         let summary = match summary {
             Some(s) => s,
             None => &self.default_summary,
         };
         debug!("Showing notification \"{}\".", summary);
-        let mut notification = notification.summary(summary).body(&body).finalize();
+        let mut notification = notification.summary(summary).body(body).finalize();
         let notification = match self.id {
             Some(id) => notification.id(id).finalize(),
             None => notification,
         };
         match notification.show() {
-            Err(e) => bail!("{:?}", e),
+            Err(e) => Err(Error::NotificationFailed(format!("{:?}", e))),
             Ok(handle) => {
                 self.id = Some(handle.id());
                 Ok(())
@@ -48,14 +65,40 @@ impl NotificationWrapper {
     }
 }
 
+/// Full and empty blocks of a bar showing a level between 0 and 100.
+pub fn build_level_blocks(level: i32, muted: bool) -> (String, String) {
+    const NUM_BLOCKS: i32 = 20;
+    let full_blocks = level * NUM_BLOCKS / 100;
+    let emtpy_blocks = NUM_BLOCKS - full_blocks;
+
+    let character = match muted {
+        true => '░',
+        false => '█',
+    };
+    (
+        (0..full_blocks).map(|_| character).collect::<String>(),
+        (0..emtpy_blocks).map(|_| character).collect::<String>(),
+    )
+}
+
+fn build_level_bar(level: i32, muted: bool) -> String {
+    let (full_blocks, empty_blocks) = build_level_blocks(level, muted);
+    format!(
+        "{}<span color=\"grey\">{}</span>",
+        full_blocks, empty_blocks
+    )
+}
+
 pub struct VolumeNotification {
     notification_handle: NotificationWrapper,
+    show_db: bool,
 }
 
 impl VolumeNotification {
-    pub fn new(id: Option<u32>) -> Self {
+    pub fn new(id: Option<u32>, show_db: bool) -> Self {
         Self {
             notification_handle: NotificationWrapper::new(id, String::from("Volume")),
+            show_db,
         }
     }
 
@@ -63,28 +106,39 @@ impl VolumeNotification {
         self.notification_handle.get_id()
     }
 
-    fn build_volume_string(info: &VolumeInfo) -> (String, String) {
-        const NUM_BLOCKS: i32 = 20;
-        let full_blocks = info.volume * NUM_BLOCKS / 100;
-        let emtpy_blocks = NUM_BLOCKS - full_blocks;
-
+    fn build_volume_string(info: &VolumeInfo, show_db: bool) -> (String, String) {
         let title = format!(
-            "Volume ({}%{})",
+            "Volume ({}%{}{}{})",
             info.volume,
+            match (show_db, info.db) {
+                (true, Some(db)) => format!(", {:.1} dB", db),
+                (true, None) => String::from(", -∞ dB"),
+                (false, _) => String::new(),
+            },
             match info.muted {
                 true => ", muted",
                 false => "",
+            },
+            match info.is_balanced() {
+                true => String::new(),
+                false => format!(", balance {}", info.balance_string()),
             }
         );
-        let character = match info.muted {
-            true => '░',
-            false => '█',
+        let body = if info.is_balanced() {
+            build_level_bar(info.volume, info.muted)
+        } else {
+            info.channels
+                .iter()
+                .map(|c| {
+                    format!(
+                        "{:<2} {}",
+                        c.short_name(),
+                        build_level_bar(c.volume, info.muted)
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("\n")
         };
-        let body = format!(
-            "{}<span color=\"grey\">{}</span>",
-            (0..full_blocks).map(|_| character).collect::<String>(),
-            (0..emtpy_blocks).map(|_| character).collect::<String>()
-        );
 
         (title, body)
     }
@@ -94,12 +148,77 @@ impl VolumeNotification {
             "Showing volume notification ({}%, muted: {}).",
             volume_info.volume, volume_info.muted
         );
-        let (title, body) = VolumeNotification::build_volume_string(&volume_info);
+        let (title, body) = VolumeNotification::build_volume_string(volume_info, self.show_db);
 
         self.notification_handle.notify(Some(&title), &body)
     }
 }
 
+pub struct LevelNotification {
+    notification_handle: NotificationWrapper,
+}
+
+impl LevelNotification {
+    pub fn new() -> Self {
+        Self {
+            notification_handle: NotificationWrapper::new(None, String::from("Level")),
+        }
+    }
+
+    pub fn notify(&mut self, source: &str, level: i32) -> Result<(), Error> {
+        let title = format!("Level of {} ({}%)", source, level);
+        self.notification_handle
+            .notify(Some(&title), &build_level_bar(level, false))
+    }
+}
+
+/// One-off messages, e.g. the summary after loading a scene.
+pub struct MessageNotification {
+    notification_handle: NotificationWrapper,
+}
+
+impl MessageNotification {
+    pub fn new() -> Self {
+        Self {
+            notification_handle: NotificationWrapper::new(None, String::from("Audio")),
+        }
+    }
+
+    pub fn notify(&mut self, summary: &str, body: &str) -> Result<(), Error> {
+        self.notification_handle.notify(Some(summary), body)
+    }
+}
+
+/// Stays open while the microphone is live.
+pub struct MicrophoneNotification {
+    notification_handle: NotificationWrapper,
+}
+
+impl MicrophoneNotification {
+    pub fn new() -> Self {
+        Self {
+            notification_handle: NotificationWrapper::new(None, String::from("Microphone")),
+        }
+    }
+
+    pub fn notify(&mut self, source: &str, live: bool) -> Result<(), Error> {
+        debug!("Showing microphone notification (live: {}).", live);
+        if live {
+            self.notification_handle.notify_with_timeout(
+                Some("Microphone live"),
+                source,
+                Timeout::Never,
+            )
+        } else {
+            self.notification_handle.notify_with_timeout(
+                Some("Microphone muted"),
+                source,
+                Timeout::Milliseconds(1000),
+            )
+        }
+    }
+}
+
 pub struct SinkNotificaton {
     notification_handle: NotificationWrapper,
 }
@@ -120,9 +239,9 @@ impl SinkNotificaton {
         self.notification_handle.notify(None, "Changing input...")
     }
 
-    pub fn notify(&mut self, interfaces: &Vec<Interface>) -> Result<(), Error> {
+    pub fn notify(&mut self, interfaces: &[Sink], now_playing: Option<&str>) -> Result<(), Error> {
         debug!("Showing sink notification.");
-        let body = interfaces
+        let mut body = interfaces
             .iter()
             .map(|i| {
                 if i.active {
@@ -133,57 +252,168 @@ impl SinkNotificaton {
             })
             .collect::<Vec<String>>()
             .join("\n");
+        if let Some(now_playing) = now_playing {
+            // Track titles are not markup.
+            let now_playing = now_playing
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;");
+            body.push_str(&format!("\n\n▶ {}", now_playing));
+        }
 
         self.notification_handle.notify(None, &body)
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SoundEvent {
+    VolumeChange,
+    Mute,
+    Unmute,
+    InputSwitched,
+    MaxVolume,
+}
+
 pub struct SoundPlayer {
-    play_sound_process: Option<process::Child>,
+    config: SoundConfig,
+    theme: SoundTheme,
+    play_sound_thread: Option<thread::JoinHandle<()>>,
 }
 
 impl SoundPlayer {
-    pub fn new() -> Self {
+    pub fn new(config: &SoundConfig) -> Self {
         SoundPlayer {
-            play_sound_process: None,
+            config: config.clone(),
+            theme: SoundTheme::new(&config.theme),
+            play_sound_thread: None,
         }
     }
 
-    pub fn play_sound(&mut self, interface: &Interface, always_play_sound: bool) {
-        debug!(
-            "Request to play sound. Interface state: {}",
-            interface.state
-        );
-        if always_play_sound || interface.state != "RUNNING" {
-            debug!("Interface is NOT running. Playing a sound.");
-            let index = interface.index.to_string();
-            if let Some(play_sound_process) = &mut self.play_sound_process {
-                match play_sound_process.try_wait() {
-                    Ok(Some(_)) => self.play_sound_process = None,
-                    Ok(None) => {
-                        debug!(
-                            "Play sound process is still in progress. Not starting another one."
-                        );
-                        return;
+    fn build_tone(event: SoundEvent) -> Sound {
+        let frequency = match event {
+            SoundEvent::VolumeChange | SoundEvent::InputSwitched => 880.0,
+            SoundEvent::Mute => 440.0,
+            SoundEvent::Unmute => 660.0,
+            SoundEvent::MaxVolume => 1320.0,
+        };
+        Sound::tone(frequency, Duration::from_millis(150))
+    }
+
+    fn load_sound(&self, event: SoundEvent) -> Option<Sound> {
+        let events = &self.config.events;
+        let setting = match event {
+            SoundEvent::VolumeChange => &events.volume_change,
+            SoundEvent::Mute => &events.mute,
+            SoundEvent::Unmute => &events.unmute,
+            SoundEvent::InputSwitched => &events.input_switched,
+            SoundEvent::MaxVolume => &events.max_volume,
+        };
+        if setting == "off" {
+            debug!("Sound for {:?} is turned off.", event);
+            return None;
+        }
+        if self.config.tone {
+            return Some(SoundPlayer::build_tone(event));
+        }
+
+        let path = if setting.contains('/') {
+            PathBuf::from(setting)
+        } else {
+            match self.theme.lookup(setting) {
+                Some(ThemeSound::File(path)) => path,
+                Some(ThemeSound::Disabled) => {
+                    debug!("Sound \"{}\" is disabled by the theme.", setting);
+                    return None;
+                }
+                None => {
+                    error!("No sound \"{}\" in the theme, playing a tone.", setting);
+                    return Some(SoundPlayer::build_tone(event));
+                }
+            }
+        };
+        match Sound::load(&path) {
+            Ok(sound) => Some(sound),
+            Err(e) => {
+                error!("Failed to load sound, playing a tone instead: {}", e);
+                Some(SoundPlayer::build_tone(event))
+            }
+        }
+    }
+
+    /// Whether the user would not hear the change otherwise. Logs the reason for the decision.
+    fn should_play_sound(&self, interface: &Sink, always_play_sound: bool) -> bool {
+        if always_play_sound {
+            debug!("Playing a sound as requested.");
+            return true;
+        }
+        if interface.state != "RUNNING" {
+            debug!("Interface is {}. Playing a sound.", interface.state);
+            return true;
+        }
+        if self.config.check_corked {
+            match list_sink_inputs() {
+                Ok(sink_inputs) => {
+                    if sink_inputs
+                        .iter()
+                        .filter(|i| i.sink == interface.index)
+                        .all(|i| i.corked)
+                    {
+                        debug!("All streams on the interface are corked. Playing a sound.");
+                        return true;
                     }
-                    Err(e) => {
-                        error!("Failed to wait for the play sound process: {}", e);
-                        return;
+                }
+                Err(e) => error!("Failed to list sink inputs: {}", e),
+            }
+        }
+        if self.config.check_peak {
+            match PeakMeter::new(&format!("{}.monitor", interface.sink_name)) {
+                Ok(meter) => {
+                    let peak = meter.read_peak(Duration::from_millis(100));
+                    if peak < self.config.peak_threshold {
+                        debug!("Interface is silent (peak {:.3}). Playing a sound.", peak);
+                        return true;
                     }
+                    debug!("Interface is playing audio (peak {:.3}).", peak);
                 }
+                Err(e) => error!("Failed to read the peak level: {}", e),
             }
+        }
+        debug!("Interface is playing audio. Not playing a sound.");
+        false
+    }
 
-            match process::Command::new("paplay")
-                .args(&[
-                    "-d",
-                    &index,
-                    "/usr/share/sounds/freedesktop/stereo/message.oga",
-                ])
-                .spawn()
-            {
-                Ok(process) => self.play_sound_process = Some(process),
-                Err(e) => error!("Failed to start play sound process: {}", e),
+    pub fn play_sound(&mut self, interface: &Sink, event: SoundEvent, always_play_sound: bool) {
+        debug!(
+            "Request to play sound for {:?}. Interface state: {}",
+            event, interface.state
+        );
+        if self.should_play_sound(interface, always_play_sound) {
+            if let Some(play_sound_thread) = &self.play_sound_thread {
+                if !play_sound_thread.is_finished() {
+                    debug!("Play sound thread is still in progress. Not starting another one.");
+                    return;
+                }
             }
+
+            let sound = match self.load_sound(event) {
+                Some(sound) => sound,
+                None => return,
+            };
+            let index = interface.index;
+            self.play_sound_thread = Some(thread::spawn(move || {
+                sound::play(sound, index).unwrap_or_else(|e| error!("Failed to play sound: {}", e));
+            }));
+        }
+    }
+}
+
+impl Drop for SoundPlayer {
+    fn drop(&mut self) {
+        // The sound is played in-process, so it has to finish before exiting.
+        if let Some(play_sound_thread) = self.play_sound_thread.take() {
+            play_sound_thread
+                .join()
+                .unwrap_or_else(|_| error!("Play sound thread panicked."));
         }
     }
 }
@@ -0,0 +1,193 @@
+use std::io::Write;
+use std::process;
+
+use log::{debug, info};
+
+use crate::error::Error;
+
+/// Something to pick, e.g. a sink.
+#[non_exhaustive]
+pub struct Choice {
+    /// Name the sound server knows it by.
+    pub name: String,
+    /// Description for display.
+    pub label: String,
+    pub active: bool,
+}
+
+impl Choice {
+    pub fn new(name: &str, label: &str, active: bool) -> Self {
+        Choice {
+            name: name.to_string(),
+            label: label.to_string(),
+            active,
+        }
+    }
+
+    /// Same as in the interface notification, with a marker for the active one. Starts with the
+    /// number of the choice, as labels are not unique, e.g. for two streams of one application.
+    fn line(&self, index: usize) -> String {
+        format!(
+            "{} {} {}",
+            index + 1,
+            if self.active { "→" } else { " " },
+            self.label
+        )
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        let text = text.trim();
+        self.name.eq_ignore_ascii_case(text) || self.label.eq_ignore_ascii_case(text)
+    }
+}
+
+/// Index of the choice a line from the picker starts with.
+fn parse_line(line: &str, choices: &[Choice]) -> Result<usize, Error> {
+    line.split_whitespace()
+        .next()
+        .and_then(|number| number.parse::<usize>().ok())
+        .filter(|number| (1..=choices.len()).contains(number))
+        .map(|number| number - 1)
+        .ok_or_else(|| Error::Unavailable(format!("There is nothing called \"{}\".", line)))
+}
+
+/// Index of the only choice with the given name or label.
+fn find_by_name(name: &str, choices: &[Choice]) -> Result<usize, Error> {
+    let matching: Vec<usize> = (0..choices.len())
+        .filter(|i| choices[*i].matches(name))
+        .collect();
+    match matching.as_slice() {
+        [index] => Ok(*index),
+        [] => Err(Error::Unavailable(format!(
+            "There is nothing called \"{}\".",
+            name
+        ))),
+        _ => Err(Error::Unavailable(format!(
+            "\"{}\" is ambiguous, it could be {}.",
+            name,
+            matching
+                .iter()
+                .map(|i| format!("\"{}\"", choices[*i].label))
+                .collect::<Vec<String>>()
+                .join(" or ")
+        ))),
+    }
+}
+
+/// How to choose: through a picker like dmenu, rofi, wofi or fzf, or by name.
+#[non_exhaustive]
+pub enum Chooser<'a> {
+    Picker(&'a str),
+    Name(&'a str),
+}
+
+/// Runs the picker command through the shell with one line per choice on stdin. Returns the
+/// chosen line, or None if the picker was cancelled.
+fn run_picker(command: &str, lines: &[String]) -> Result<Option<String>, Error> {
+    debug!("Picking with \"{}\".", command);
+    let mut child = process::Command::new("sh")
+        .args(["-c", command])
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // Pickers that exit right away do not read everything.
+        stdin
+            .write_all(format!("{}\n", lines.join("\n")).as_bytes())
+            .unwrap_or_else(|e| debug!("Could not write all choices to the picker: {}", e));
+    }
+    let output = child.wait_with_output()?;
+    let chosen = String::from_utf8_lossy(&output.stdout)
+        .trim_end()
+        .to_string();
+    if !chosen.is_empty() {
+        return Ok(Some(chosen));
+    }
+    // dmenu, rofi and fzf exit with 1 if nothing was chosen, fzf with 130 on escape. Anything
+    // else, like 127 from the shell for a missing picker, is an error.
+    match output.status.code() {
+        Some(0) | Some(1) | Some(130) => {
+            info!("Nothing was picked.");
+            Ok(None)
+        }
+        _ => Err(Error::CommandFailed {
+            command: command.to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        }),
+    }
+}
+
+/// Returns the index of the chosen entry, or None if the picker was cancelled. A name has to match
+/// exactly one entry.
+pub fn choose(chooser: &Chooser, choices: &[Choice]) -> Result<Option<usize>, Error> {
+    if choices.is_empty() {
+        return Err(Error::Unavailable(String::from(
+            "There is nothing to pick.",
+        )));
+    }
+    match chooser {
+        Chooser::Picker(command) => {
+            let lines: Vec<String> = choices.iter().enumerate().map(|(i, c)| c.line(i)).collect();
+            match run_picker(command, &lines)? {
+                Some(line) => parse_line(&line, choices).map(Some),
+                None => Ok(None),
+            }
+        }
+        Chooser::Name(name) => find_by_name(name, choices).map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn choices() -> Vec<Choice> {
+        vec![
+            Choice::new("41", "Firefox", false),
+            Choice::new("42", "Firefox", true),
+            Choice::new("alsa_output.hdmi", "HDMI", false),
+        ]
+    }
+
+    #[test]
+    fn maps_picker_lines_back_by_number() {
+        let choices = choices();
+        assert_eq!(choices[1].line(1), "2 → Firefox");
+        for (index, choice) in choices.iter().enumerate() {
+            assert_eq!(parse_line(&choice.line(index), &choices).unwrap(), index);
+        }
+        assert!(parse_line("4   HDMI", &choices).is_err());
+        assert!(parse_line("HDMI", &choices).is_err());
+    }
+
+    #[test]
+    fn finds_only_unique_names() {
+        let choices = choices();
+        assert_eq!(find_by_name("hdmi", &choices).unwrap(), 2);
+        assert_eq!(find_by_name("42", &choices).unwrap(), 1);
+        match find_by_name("Firefox", &choices) {
+            Err(Error::Unavailable(message)) => assert_eq!(
+                message,
+                "\"Firefox\" is ambiguous, it could be \"Firefox\" or \"Firefox\"."
+            ),
+            _ => panic!("Ambiguous name accepted."),
+        }
+        assert!(find_by_name("Speakers", &choices).is_err());
+    }
+
+    #[test]
+    fn reports_failing_pickers() {
+        let lines = vec![String::from("1 → HDMI"), String::from("2   Speakers")];
+        assert_eq!(
+            run_picker("sed -n 2p", &lines).unwrap(),
+            Some(String::from("2   Speakers"))
+        );
+        assert_eq!(run_picker("false", &lines).unwrap(), None);
+        assert_eq!(run_picker("exit 130", &lines).unwrap(), None);
+        match run_picker("echo broken >&2; exit 127", &lines) {
+            Err(Error::CommandFailed { stderr, .. }) => assert_eq!(stderr, "broken"),
+            _ => panic!("Failing picker accepted."),
+        }
+    }
+}
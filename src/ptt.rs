@@ -0,0 +1,194 @@
+use std::io;
+use std::process;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use chrono::Local;
+use log::{debug, error, info, warn};
+
+use crate::error::Error;
+use crate::state::{load_push_to_talk, update_push_to_talk, PushToTalk};
+use crate::volume_control::VolumeControl;
+
+/// Sent by `ptt stop` to the push-to-talk process.
+const RELEASE_SIGNAL: libc::c_int = libc::SIGUSR1;
+
+/// End push-to-talk and are sent on to the previous handler once the source is muted.
+const SIGNALS: [libc::c_int; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+/// How long a `ptt stop` while nothing is live cancels the next `ptt start`, in milliseconds.
+const EARLY_RELEASE_MS: i64 = 500;
+
+static RECEIVED_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn handle_signal(signal: libc::c_int) {
+    RECEIVED_SIGNAL.store(signal, Ordering::SeqCst);
+}
+
+fn is_running(pid: u32) -> bool {
+    // Signal 0 only checks whether the process exists.
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    result == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Mutes the microphone if the push-to-talk process is gone without doing so, e.g. because it
+/// was killed. Called on every run and regularly by the daemon.
+pub fn recover() -> Result<(), Error> {
+    if let Some(PushToTalk::Live { pid, source }) = load_push_to_talk() {
+        if !is_running(pid) {
+            warn!("Push-to-talk process {} is gone, muting {}.", pid, source);
+            VolumeControl::new()?.set_source_mute(&source, true)?;
+            update_push_to_talk(|push_to_talk| {
+                if matches!(push_to_talk, Some(PushToTalk::Live { pid: p, .. }) if *p == pid) {
+                    *push_to_talk = None;
+                }
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Catches the release signal and the signals that end push-to-talk. Restores the previous
+/// handlers when dropped.
+struct SignalHandlers(Vec<(libc::c_int, libc::sighandler_t)>);
+
+impl SignalHandlers {
+    fn install() -> Self {
+        RECEIVED_SIGNAL.store(0, Ordering::SeqCst);
+        SignalHandlers(
+            SIGNALS
+                .iter()
+                .chain(&[RELEASE_SIGNAL])
+                .map(|signal| {
+                    let previous = unsafe {
+                        libc::signal(
+                            *signal,
+                            handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+                        )
+                    };
+                    (*signal, previous)
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Drop for SignalHandlers {
+    fn drop(&mut self) {
+        for (signal, handler) in &self.0 {
+            unsafe { libc::signal(*signal, *handler) };
+        }
+    }
+}
+
+/// Keeps a source unmuted. Mutes it again when dropped, also when unwinding from a panic.
+pub struct LiveSource<'a> {
+    volume_control: &'a VolumeControl,
+    source: String,
+    // Dropped after the source is muted and the record is cleared.
+    _signal_handlers: SignalHandlers,
+}
+
+impl<'a> LiveSource<'a> {
+    /// Records the source in the state file before unmuting it, so that it can always be muted
+    /// again. Returns None if `ptt stop` came first.
+    pub fn unmute(volume_control: &'a VolumeControl, source: &str) -> Result<Option<Self>, Error> {
+        // `ptt stop` sends the release signal as soon as it finds the record.
+        let signal_handlers = SignalHandlers::install();
+        let now = Local::now().timestamp_millis();
+        let started = update_push_to_talk(|push_to_talk| match push_to_talk {
+            Some(PushToTalk::Live { pid, .. }) => Err(Error::Usage(format!(
+                "Push-to-talk is already active in process {}.",
+                pid
+            ))),
+            Some(PushToTalk::Released { time }) if now - *time < EARLY_RELEASE_MS => {
+                *push_to_talk = None;
+                Ok(false)
+            }
+            _ => {
+                *push_to_talk = Some(PushToTalk::Live {
+                    pid: process::id(),
+                    source: source.to_string(),
+                });
+                Ok(true)
+            }
+        })??;
+        if !started {
+            info!("Push-to-talk was released before it started.");
+            return Ok(None);
+        }
+        let live_source = LiveSource {
+            volume_control,
+            source: source.to_string(),
+            _signal_handlers: signal_handlers,
+        };
+        volume_control.set_source_mute(source, false)?;
+        info!("Microphone {} is live.", source);
+        Ok(Some(live_source))
+    }
+
+    /// Blocks until `ptt stop` or a signal ends push-to-talk. Returns the signal, if it was not
+    /// the one of `ptt stop`.
+    pub fn wait_for_release(&self) -> Option<libc::c_int> {
+        const POLL_INTERVAL: Duration = Duration::from_millis(20);
+        while RECEIVED_SIGNAL.load(Ordering::SeqCst) == 0 {
+            thread::sleep(POLL_INTERVAL);
+        }
+        match RECEIVED_SIGNAL.load(Ordering::SeqCst) {
+            RELEASE_SIGNAL => None,
+            signal => {
+                debug!("Push-to-talk ended by signal {}.", signal);
+                Some(signal)
+            }
+        }
+    }
+}
+
+impl<'a> Drop for LiveSource<'a> {
+    fn drop(&mut self) {
+        self.volume_control
+            .set_source_mute(&self.source, true)
+            .unwrap_or_else(|e| error!("Failed to mute {}: {}", self.source, e));
+        info!("Microphone {} is muted.", self.source);
+        let pid = process::id();
+        update_push_to_talk(|push_to_talk| {
+            if matches!(push_to_talk, Some(PushToTalk::Live { pid: p, .. }) if *p == pid) {
+                *push_to_talk = None;
+            }
+        })
+        .unwrap_or_else(|e| error!("Failed to end push-to-talk: {}", e));
+    }
+}
+
+/// Sends a signal that ended push-to-talk on to the previous handler, once the source is muted.
+pub fn forward_signal(signal: libc::c_int) {
+    unsafe { libc::raise(signal) };
+}
+
+/// Ends push-to-talk, wherever it was started. The source is muted right away instead of waiting
+/// for the push-to-talk process to notice. If nothing is live yet, the next `ptt start` is
+/// cancelled, as the key was released already.
+pub fn release(volume_control: &VolumeControl) -> Result<(), Error> {
+    let now = Local::now().timestamp_millis();
+    let live = update_push_to_talk(|push_to_talk| match push_to_talk.take() {
+        Some(PushToTalk::Live { pid, source }) => Some((pid, source)),
+        _ => {
+            *push_to_talk = Some(PushToTalk::Released { time: now });
+            None
+        }
+    })?;
+    match live {
+        Some((pid, source)) => {
+            volume_control.set_source_mute(&source, true)?;
+            if unsafe { libc::kill(pid as libc::pid_t, RELEASE_SIGNAL) } != 0 {
+                debug!("Push-to-talk process {} is gone.", pid);
+            }
+            Ok(())
+        }
+        None => {
+            debug!("Push-to-talk is not active.");
+            Ok(())
+        }
+    }
+}
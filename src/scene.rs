@@ -0,0 +1,231 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::volume::cap_channel_volumes;
+use crate::volume_control::{list_cards, list_sink_inputs, VolumeControl};
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SinkState {
+    pub name: String,
+    /// Volume of each channel in percent.
+    pub volumes: Vec<i32>,
+    pub muted: bool,
+    pub port: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct CardState {
+    pub name: String,
+    pub profile: String,
+}
+
+/// The sink the streams of an application play on.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct StreamRoute {
+    pub application: String,
+    pub sink: String,
+}
+
+/// A snapshot of the routing and the volumes. Sinks and cards are stored by name, as their
+/// indices change whenever a device is plugged in again.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Scene {
+    pub default_sink: Option<String>,
+    pub cards: Vec<CardState>,
+    pub sinks: Vec<SinkState>,
+    pub streams: Vec<StreamRoute>,
+}
+
+/// What loading a scene did, for the summary.
+pub struct SceneSummary {
+    pub default_sink: Option<String>,
+    pub sinks: usize,
+    pub streams: usize,
+    pub failures: usize,
+}
+
+fn get_scene_file_path(name: &str) -> Result<PathBuf, Error> {
+    if name.is_empty() || name.contains('/') {
+        return Err(Error::Usage(format!("Invalid scene name \"{}\".", name)));
+    }
+    let home_folder = env::var("HOME")?;
+    Ok(Path::new(&home_folder)
+        .join(".config/audio_tools/scenes")
+        .join(format!("{}.toml", name)))
+}
+
+/// Logs a failed step and counts it, loading goes on with the next one.
+fn count_failure(failures: &mut usize, result: Result<(), Error>) -> bool {
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            error!("{}", e);
+            *failures += 1;
+            false
+        }
+    }
+}
+
+impl Scene {
+    pub fn capture(volume_control: &mut VolumeControl) -> Result<Self, Error> {
+        let interfaces = volume_control.get_available_interfaces()?.clone();
+        let cards = list_cards()?
+            .into_iter()
+            .filter_map(|c| {
+                let name = c.name;
+                c.profile.map(|profile| CardState { name, profile })
+            })
+            .collect();
+        let sinks = interfaces
+            .iter()
+            .map(|i| SinkState {
+                name: i.sink_name.clone(),
+                volumes: i.volumes.clone(),
+                muted: i.muted,
+                port: i.port.clone(),
+            })
+            .collect();
+        let mut streams: Vec<StreamRoute> = Vec::new();
+        for sink_input in list_sink_inputs()? {
+            let sink = interfaces.iter().find(|i| i.index == sink_input.sink);
+            if let Some(sink) = sink {
+                if sink_input.application.is_empty()
+                    || streams
+                        .iter()
+                        .any(|s| s.application == sink_input.application)
+                {
+                    continue;
+                }
+                streams.push(StreamRoute {
+                    application: sink_input.application,
+                    sink: sink.sink_name.clone(),
+                });
+            }
+        }
+
+        Ok(Scene {
+            default_sink: interfaces
+                .iter()
+                .find(|i| i.active)
+                .map(|i| i.sink_name.clone()),
+            cards,
+            sinks,
+            streams,
+        })
+    }
+
+    pub fn load(name: &str) -> Result<Self, Error> {
+        let path = get_scene_file_path(name)?;
+        if !path.exists() {
+            return Err(Error::Unavailable(format!(
+                "There is no scene \"{}\".",
+                name
+            )));
+        }
+        let content = fs::read_to_string(&path)?;
+        toml::from_str(&content).map_err(|e| Error::toml(&content, e))
+    }
+
+    pub fn save(&self, name: &str) -> Result<(), Error> {
+        let path = get_scene_file_path(name)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, toml::to_string_pretty(self)?)?;
+        info!("Saved scene \"{}\" to {:?}.", name, path);
+        Ok(())
+    }
+
+    /// Applies as much of the scene as possible. Sinks that are not connected right now are
+    /// skipped. Sink volumes are lowered to the cap of the active volume profile.
+    pub fn apply(
+        &self,
+        volume_control: &mut VolumeControl,
+        max_volume: Option<i32>,
+    ) -> Result<SceneSummary, Error> {
+        let mut failures = 0;
+        // Profiles come first, they decide which sinks exist.
+        for card in &self.cards {
+            debug!("Setting profile of {} to {}.", card.name, card.profile);
+            count_failure(
+                &mut failures,
+                volume_control.set_card_profile(&card.name, &card.profile),
+            );
+        }
+
+        let interfaces = volume_control.get_available_interfaces()?.clone();
+        let mut sinks = 0;
+        for sink in &self.sinks {
+            if !interfaces.iter().any(|i| i.sink_name == sink.name) {
+                warn!("Sink {} of the scene is not available.", sink.name);
+                continue;
+            }
+            let mut applied = true;
+            if let Some(port) = &sink.port {
+                applied &= count_failure(
+                    &mut failures,
+                    volume_control.set_sink_port(&sink.name, port),
+                );
+            }
+            if !sink.volumes.is_empty() {
+                applied &= count_failure(
+                    &mut failures,
+                    volume_control.set_sink_volumes(
+                        &sink.name,
+                        &cap_channel_volumes(&sink.volumes, max_volume),
+                    ),
+                );
+            }
+            applied &= count_failure(
+                &mut failures,
+                volume_control.set_sink_mute(&sink.name, sink.muted),
+            );
+            if applied {
+                sinks += 1;
+            }
+        }
+
+        let mut default_sink = None;
+        if let Some(sink) = &self.default_sink {
+            if count_failure(&mut failures, volume_control.set_default_sink(sink)) {
+                default_sink = volume_control
+                    .active_interface
+                    .as_ref()
+                    .map(|i| i.name.clone());
+            }
+        }
+
+        let mut streams = 0;
+        for sink_input in list_sink_inputs()? {
+            let route = self
+                .streams
+                .iter()
+                .find(|s| s.application == sink_input.application);
+            let target = route.and_then(|r| interfaces.iter().find(|i| i.sink_name == r.sink));
+            if let Some(target) = target {
+                if target.index != sink_input.sink
+                    && count_failure(
+                        &mut failures,
+                        volume_control.move_sink_input(sink_input.index, &target.sink_name),
+                    )
+                {
+                    streams += 1;
+                }
+            }
+        }
+
+        Ok(SceneSummary {
+            default_sink,
+            sinks,
+            streams,
+            failures,
+        })
+    }
+}
@@ -0,0 +1,173 @@
+use std::thread;
+use std::time::Duration;
+
+use chrono::{Local, NaiveTime, TimeZone};
+use log::{error, info, warn};
+
+use crate::config::Config;
+use crate::daemon;
+use crate::error::Error;
+use crate::ptt;
+use crate::state::{load_schedule, update_schedule, ScheduledCommand};
+use crate::volume::VolumeInfo;
+
+/// Parses durations like "90s", "30m" or "2h". Plain numbers are minutes.
+pub fn parse_delay(delay: &str) -> Result<Duration, Error> {
+    let (number, factor) = match delay.chars().last() {
+        Some('s') => (&delay[..delay.len() - 1], 1),
+        Some('m') => (&delay[..delay.len() - 1], 60),
+        Some('h') => (&delay[..delay.len() - 1], 60 * 60),
+        _ => (delay, 60),
+    };
+    let number = number.parse::<u64>().map_err(|e| Error::parse(delay, e))?;
+    Ok(Duration::from_secs(number * factor))
+}
+
+/// Unix timestamp of the next time the clock shows the given time, e.g. "23:00".
+pub fn next_time_of_day(time: &str) -> Result<i64, Error> {
+    let time = NaiveTime::parse_from_str(time, "%H:%M").map_err(|e| Error::parse(time, e))?;
+    let now = Local::now();
+    let mut date = now.date_naive();
+    if now.time() >= time {
+        date = date
+            .succ_opt()
+            .ok_or_else(|| Error::parse(time.to_string(), "Invalid date."))?;
+    }
+    let date_time = Local
+        .from_local_datetime(&date.and_time(time))
+        .earliest()
+        .ok_or_else(|| Error::parse(time.to_string(), "This time does not exist today."))?;
+    Ok(date_time.timestamp())
+}
+
+pub fn add(time: i64, args: &[String]) -> Result<(), Error> {
+    update_schedule(|schedule| {
+        schedule.push(ScheduledCommand {
+            time,
+            args: args.to_vec(),
+        });
+        schedule.sort_by_key(|s| s.time);
+    })
+}
+
+/// The command line, with arguments containing spaces quoted.
+fn describe(args: &[String]) -> String {
+    args.iter()
+        .map(|a| {
+            if a.contains(' ') {
+                format!("\"{}\"", a)
+            } else {
+                a.clone()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+pub fn list() {
+    let schedule = load_schedule();
+    if schedule.is_empty() {
+        println!("Nothing scheduled.");
+    }
+    for scheduled in schedule {
+        match Local.timestamp_opt(scheduled.time, 0).single() {
+            Some(time) => println!(
+                "{}  {}",
+                time.format("%Y-%m-%d %H:%M:%S"),
+                describe(&scheduled.args)
+            ),
+            None => println!("{}  {}", scheduled.time, describe(&scheduled.args)),
+        }
+    }
+}
+
+pub fn clear() -> Result<(), Error> {
+    update_schedule(|schedule| schedule.clear())
+}
+
+/// Runs the commands that are due and removes them from the schedule. Commands missed by more
+/// than a minute, e.g. while the daemon was not running, are dropped, as muting or fading out
+/// hours late would only surprise.
+fn run_due_commands(config: &Config) -> Result<(), Error> {
+    const GRACE_PERIOD: i64 = 60;
+    let now = Local::now().timestamp();
+    let due: Vec<ScheduledCommand> = update_schedule(|schedule| {
+        let (due, pending) = schedule.drain(..).partition(|s| s.time <= now);
+        *schedule = pending;
+        due
+    })?;
+    let (due, missed): (Vec<ScheduledCommand>, Vec<ScheduledCommand>) =
+        due.into_iter().partition(|s| s.time >= now - GRACE_PERIOD);
+    for scheduled in missed {
+        warn!(
+            "Dropping scheduled command \"{}\", it was due {} seconds ago.",
+            describe(&scheduled.args),
+            now - scheduled.time
+        );
+    }
+    for scheduled in due {
+        info!(
+            "Running scheduled command \"{}\".",
+            describe(&scheduled.args)
+        );
+        let config = config.clone();
+        // Commands like fades take a while, do not hold up the schedule.
+        thread::spawn(move || {
+            daemon::execute_args(&config, &scheduled.args)
+                .unwrap_or_else(|e| error!("Scheduled command failed: {}", e));
+        });
+    }
+    Ok(())
+}
+
+/// Lowers the volume if it is above the cap of the active profile.
+fn enforce_max_volume(config: &Config) -> Result<(), Error> {
+    if let Some(max_volume) = config.volume.get_max_volume(Local::now().time()) {
+        let volume = VolumeInfo::get_volume()?;
+        if volume.volume > max_volume {
+            info!(
+                "Volume {}% is above the cap of {}%, lowering it.",
+                volume.volume, max_volume
+            );
+            daemon::execute(config, &format!("fade {} --duration 1000", max_volume))?;
+        }
+    }
+    Ok(())
+}
+
+/// Enforces the schedule and the volume profiles, called regularly by the daemon. Also mutes
+/// the microphone if a push-to-talk process died.
+pub fn run(config: &Config) {
+    const INTERVAL: Duration = Duration::from_secs(5);
+    info!("Enforcing the schedule and the volume profiles.");
+    loop {
+        ptt::recover().unwrap_or_else(|e| error!("Failed to recover push-to-talk: {}", e));
+        run_due_commands(config).unwrap_or_else(|e| error!("Failed to run the schedule: {}", e));
+        enforce_max_volume(config)
+            .unwrap_or_else(|e| error!("Failed to enforce the volume cap: {}", e));
+        thread::sleep(INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_delays() {
+        assert_eq!(parse_delay("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_delay("30m").unwrap(), Duration::from_secs(30 * 60));
+        assert_eq!(parse_delay("2h").unwrap(), Duration::from_secs(2 * 60 * 60));
+        assert_eq!(parse_delay("5").unwrap(), Duration::from_secs(5 * 60));
+        assert!(parse_delay("soon").is_err());
+        assert!(parse_delay("-5m").is_err());
+    }
+
+    #[test]
+    fn finds_the_next_time_of_day() {
+        let now = Local::now().timestamp();
+        let time = next_time_of_day("12:00").unwrap();
+        assert!(time > now && time <= now + 24 * 60 * 60 + 60 * 60);
+        assert!(next_time_of_day("12").is_err());
+    }
+}
@@ -0,0 +1,336 @@
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use log::{debug, error};
+
+use crate::error::Error;
+use crate::volume_control::{parse_sink_inputs, run_backend};
+
+/// The sound server only takes the target of a new stream from the environment, which cannot be
+/// changed while other threads run. Instead, each new stream is moved to its sink or source once
+/// it appears. Only one stream is created at a time, so that they cannot be mixed up.
+static NEW_STREAM: Mutex<()> = Mutex::new(());
+
+/// Playback or recording streams, as named by pacmd.
+#[derive(Clone, Copy)]
+enum StreamKind {
+    SinkInput,
+    SourceOutput,
+}
+
+impl StreamKind {
+    fn name(self) -> &'static str {
+        match self {
+            StreamKind::SinkInput => "sink-input",
+            StreamKind::SourceOutput => "source-output",
+        }
+    }
+}
+
+/// Indexes of the streams of this process. Source outputs are listed like sink inputs.
+fn own_streams(kind: StreamKind) -> Result<Vec<i32>, Error> {
+    let output = run_backend("pacmd", &[format!("list-{}s", kind.name())])?;
+    let pid = process::id();
+    Ok(parse_sink_inputs(&output)?
+        .into_iter()
+        .filter(|s| s.process_id == Some(pid))
+        .map(|s| s.index)
+        .collect())
+}
+
+/// Waits for a stream of this process that is not one of the given ones and moves it.
+fn move_new_stream(kind: StreamKind, existing: &[i32], target: &str) -> Result<(), Error> {
+    const TIMEOUT: Duration = Duration::from_secs(1);
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        let new_stream = own_streams(kind)?
+            .into_iter()
+            .find(|index| !existing.contains(index));
+        if let Some(index) = new_stream {
+            debug!("Moving {} {} to {}.", kind.name(), index, target);
+            run_backend(
+                "pacmd",
+                &[
+                    format!("move-{}", kind.name()),
+                    index.to_string(),
+                    target.to_string(),
+                ],
+            )?;
+            return Ok(());
+        }
+        if Instant::now() > deadline {
+            return Err(Error::Unavailable(format!(
+                "The new {} did not appear on the sound server.",
+                kind.name()
+            )));
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// Decoded audio as interleaved 16 bit samples.
+pub struct Sound {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples: Vec<i16>,
+}
+
+fn scale_to_i16(sample: i32, bits_per_sample: u32) -> i16 {
+    if bits_per_sample > 16 {
+        (sample >> (bits_per_sample - 16)) as i16
+    } else {
+        (sample << (16 - bits_per_sample)) as i16
+    }
+}
+
+impl Sound {
+    /// Decodes a WAV, OGG or FLAC file, based on its extension.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        debug!("Decoding sound file {:?}.", path);
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "wav" => Sound::load_wav(path),
+            "ogg" | "oga" => Sound::load_ogg(path),
+            "flac" => Sound::load_flac(path),
+            _ => Err(Error::Audio(format!("Unsupported sound file {:?}.", path))),
+        }
+    }
+
+    fn load_wav(path: &Path) -> Result<Self, Error> {
+        Sound::decode_wav(BufReader::new(File::open(path)?))
+    }
+
+    /// Decodes WAV data up to the first unreadable sample. Programs writing WAV to a pipe cannot
+    /// fill in the data length, so it is not trusted.
+    pub fn decode_wav<R: Read>(reader: R) -> Result<Self, Error> {
+        let mut reader = hound::WavReader::new(reader)?;
+        let spec = reader.spec();
+        let samples = match spec.sample_format {
+            hound::SampleFormat::Int => reader
+                .samples::<i32>()
+                .map_while(Result::ok)
+                .map(|s| scale_to_i16(s, u32::from(spec.bits_per_sample)))
+                .collect(),
+            hound::SampleFormat::Float => reader
+                .samples::<f32>()
+                .map_while(Result::ok)
+                .map(|s| (s * f32::from(i16::MAX)) as i16)
+                .collect(),
+        };
+        Ok(Self {
+            sample_rate: spec.sample_rate,
+            channels: spec.channels,
+            samples,
+        })
+    }
+
+    fn load_ogg(path: &Path) -> Result<Self, Error> {
+        let mut reader = lewton::inside_ogg::OggStreamReader::new(File::open(path)?)?;
+        let mut samples = Vec::new();
+        while let Some(packet) = reader.read_dec_packet_itl()? {
+            samples.extend(packet);
+        }
+        Ok(Self {
+            sample_rate: reader.ident_hdr.audio_sample_rate,
+            channels: u16::from(reader.ident_hdr.audio_channels),
+            samples,
+        })
+    }
+
+    fn load_flac(path: &Path) -> Result<Self, Error> {
+        let mut reader = claxon::FlacReader::open(path)?;
+        let info = reader.streaminfo();
+        let samples = reader
+            .samples()
+            .map(|s| s.map(|s| scale_to_i16(s, info.bits_per_sample)))
+            .collect::<Result<Vec<i16>, _>>()?;
+        Ok(Self {
+            sample_rate: info.sample_rate,
+            channels: info.channels as u16,
+            samples,
+        })
+    }
+
+    /// A short sine beep that fades in and out to avoid clicks.
+    pub fn tone(frequency: f32, duration: Duration) -> Self {
+        const SAMPLE_RATE: u32 = 44100;
+        const AMPLITUDE: f32 = 0.3;
+        const FADE_SAMPLES: f32 = 441.0;
+
+        let num_samples = (duration.as_secs_f32() * SAMPLE_RATE as f32) as usize;
+        let samples = (0..num_samples)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                let fade = (i as f32 / FADE_SAMPLES)
+                    .min((num_samples - i) as f32 / FADE_SAMPLES)
+                    .min(1.0);
+                let value = (2.0 * PI * frequency * t).sin() * AMPLITUDE * fade;
+                (value * f32::from(i16::MAX)) as i16
+            })
+            .collect();
+        Self {
+            sample_rate: SAMPLE_RATE,
+            channels: 1,
+            samples,
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        let frames = self.samples.len() as u64 / u64::from(self.channels.max(1));
+        Duration::from_millis(frames * 1000 / u64::from(self.sample_rate.max(1)))
+    }
+}
+
+fn find_output_device(host: &cpal::Host) -> Result<cpal::Device, Error> {
+    // The pulse device lets the sound server route the stream to the requested sink.
+    let pulse_device = host
+        .output_devices()?
+        .find(|d| d.name().map(|n| n == "pulse").unwrap_or(false));
+    match pulse_device {
+        Some(device) => Ok(device),
+        None => host
+            .default_output_device()
+            .ok_or_else(|| Error::Unavailable(String::from("No audio output device available."))),
+    }
+}
+
+/// Plays the sound on the sink with the given index and blocks until it is done.
+pub fn play(sound: Sound, sink_index: i32) -> Result<(), Error> {
+    let new_stream = NEW_STREAM.lock().unwrap_or_else(|e| e.into_inner());
+    let existing = own_streams(StreamKind::SinkInput)?;
+
+    let host = cpal::default_host();
+    let device = find_output_device(&host)?;
+    let config = cpal::StreamConfig {
+        channels: sound.channels,
+        sample_rate: cpal::SampleRate(sound.sample_rate),
+        buffer_size: cpal::BufferSize::Default,
+    };
+    let duration = sound.duration();
+
+    let (done_sender, done_receiver) = mpsc::channel();
+    // Silence until the stream is on the right sink.
+    let moved = Arc::new(AtomicBool::new(false));
+    let playing = moved.clone();
+    let mut position = 0;
+    let stream = device.build_output_stream(
+        &config,
+        move |data: &mut [i16], _: &cpal::OutputCallbackInfo| {
+            if !playing.load(Ordering::SeqCst) {
+                data.fill(0);
+                return;
+            }
+            for sample in data.iter_mut() {
+                *sample = sound.samples.get(position).copied().unwrap_or(0);
+                position += 1;
+            }
+            if position >= sound.samples.len() {
+                done_sender.send(()).unwrap_or(());
+            }
+        },
+        |e| error!("Error while playing sound: {}", e),
+        None,
+    )?;
+    stream.play()?;
+    move_new_stream(StreamKind::SinkInput, &existing, &sink_index.to_string())
+        .unwrap_or_else(|e| error!("Failed to move the sound to sink {}: {}", sink_index, e));
+    moved.store(true, Ordering::SeqCst);
+    drop(new_stream);
+
+    done_receiver
+        .recv_timeout(duration + Duration::from_secs(1))
+        .unwrap_or_else(|_| error!("Timed out while playing sound."));
+    // Give the sound server some time to drain its buffers.
+    thread::sleep(Duration::from_millis(100));
+    Ok(())
+}
+
+/// Reads peak levels between 0 and 1 from a source, e.g. the monitor of a sink.
+pub struct PeakMeter {
+    _stream: cpal::Stream,
+    receiver: mpsc::Receiver<f32>,
+}
+
+impl PeakMeter {
+    pub fn new(source_name: &str) -> Result<Self, Error> {
+        let _new_stream = NEW_STREAM.lock().unwrap_or_else(|e| e.into_inner());
+        let existing = own_streams(StreamKind::SourceOutput)?;
+
+        let host = cpal::default_host();
+        let pulse_device = host
+            .input_devices()?
+            .find(|d| d.name().map(|n| n == "pulse").unwrap_or(false));
+        let device = match pulse_device {
+            Some(device) => device,
+            None => host.default_input_device().ok_or_else(|| {
+                Error::Unavailable(String::from("No audio input device available."))
+            })?,
+        };
+        let config = cpal::StreamConfig {
+            channels: 1,
+            sample_rate: cpal::SampleRate(8000),
+            buffer_size: cpal::BufferSize::Default,
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        // Peaks of the default source until the stream is on the right one.
+        let moved = Arc::new(AtomicBool::new(false));
+        let reading = moved.clone();
+        let stream = device.build_input_stream(
+            &config,
+            move |data: &[i16], _: &cpal::InputCallbackInfo| {
+                if !reading.load(Ordering::SeqCst) {
+                    return;
+                }
+                let peak = data.iter().map(|s| s.unsigned_abs()).max().unwrap_or(0);
+                sender
+                    .send(f32::from(peak) / f32::from(i16::MAX))
+                    .unwrap_or(());
+            },
+            |e| error!("Error while reading peak levels: {}", e),
+            None,
+        )?;
+        stream.play()?;
+        move_new_stream(StreamKind::SourceOutput, &existing, source_name)?;
+        moved.store(true, Ordering::SeqCst);
+        Ok(Self {
+            _stream: stream,
+            receiver,
+        })
+    }
+
+    /// Converts a peak to a level between 0 and 100 on a -60 dB to 0 dB scale.
+    pub fn peak_to_level(peak: f32) -> i32 {
+        const RANGE_DB: f32 = 60.0;
+        if peak <= 0.0 {
+            return 0;
+        }
+        let db = 20.0 * peak.log10();
+        ((db + RANGE_DB) / RANGE_DB * 100.0).clamp(0.0, 100.0) as i32
+    }
+
+    /// Highest peak within the given duration.
+    pub fn read_peak(&self, duration: Duration) -> f32 {
+        let deadline = Instant::now() + duration;
+        let mut peak: f32 = 0.0;
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            match self.receiver.recv_timeout(remaining) {
+                Ok(level) => peak = peak.max(level),
+                Err(_) => break,
+            }
+        }
+        peak
+    }
+}
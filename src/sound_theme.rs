@@ -0,0 +1,129 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::debug;
+
+const FALLBACK_THEME: &str = "freedesktop";
+const EXTENSIONS: [&str; 4] = ["disabled", "oga", "ogg", "wav"];
+
+pub enum ThemeSound {
+    File(PathBuf),
+    Disabled,
+}
+
+/// Looks up sounds according to the XDG Sound Theme specification.
+pub struct SoundTheme {
+    base_dirs: Vec<PathBuf>,
+    themes: Vec<(PathBuf, Vec<String>)>,
+}
+
+fn get_base_dirs() -> Vec<PathBuf> {
+    let mut base_dirs = Vec::new();
+    match env::var("XDG_DATA_HOME") {
+        Ok(data_home) if !data_home.is_empty() => base_dirs.push(PathBuf::from(data_home)),
+        _ => {
+            if let Ok(home_folder) = env::var("HOME") {
+                base_dirs.push(Path::new(&home_folder).join(".local/share"));
+            }
+        }
+    }
+    let data_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|d| !d.is_empty())
+        .unwrap_or_else(|| String::from("/usr/local/share:/usr/share"));
+    base_dirs.extend(data_dirs.split(':').map(PathBuf::from));
+    base_dirs.into_iter().map(|d| d.join("sounds")).collect()
+}
+
+/// Reads the inherited themes and the sound directories of a theme's index.theme.
+fn read_index(theme_dir: &Path) -> (Vec<String>, Vec<String>) {
+    let mut inherits = Vec::new();
+    let mut directories = Vec::new();
+    if let Ok(content) = fs::read_to_string(theme_dir.join("index.theme")) {
+        for line in content.lines() {
+            let split_list = |value: &str| {
+                value
+                    .split(',')
+                    .map(|v| v.trim().to_string())
+                    .filter(|v| !v.is_empty())
+                    .collect::<Vec<String>>()
+            };
+            if let Some(value) = line.strip_prefix("Inherits=") {
+                inherits = split_list(value);
+            } else if let Some(value) = line.strip_prefix("Directories=") {
+                directories = split_list(value);
+            }
+        }
+    }
+    if directories.is_empty() {
+        directories.push(String::from("stereo"));
+    }
+    (inherits, directories)
+}
+
+impl SoundTheme {
+    pub fn new(name: &str) -> Self {
+        let mut theme = SoundTheme {
+            base_dirs: get_base_dirs(),
+            themes: Vec::new(),
+        };
+        let mut visited = HashSet::new();
+        theme.add_theme(name, &mut visited);
+        theme.add_theme(FALLBACK_THEME, &mut visited);
+        theme
+    }
+
+    fn add_theme(&mut self, name: &str, visited: &mut HashSet<String>) {
+        if !visited.insert(name.to_string()) {
+            return;
+        }
+        let mut inherited = Vec::new();
+        for base_dir in &self.base_dirs {
+            let theme_dir = base_dir.join(name);
+            if theme_dir.is_dir() {
+                let (inherits, directories) = read_index(&theme_dir);
+                inherited.extend(inherits);
+                self.themes.push((theme_dir, directories));
+            }
+        }
+        for inherited_name in inherited {
+            self.add_theme(&inherited_name, visited);
+        }
+    }
+
+    fn lookup_exact(&self, name: &str) -> Option<ThemeSound> {
+        for (theme_dir, directories) in &self.themes {
+            for directory in directories {
+                for extension in EXTENSIONS.iter() {
+                    let path = theme_dir
+                        .join(directory)
+                        .join(format!("{}.{}", name, extension));
+                    if path.is_file() {
+                        return Some(match *extension {
+                            "disabled" => ThemeSound::Disabled,
+                            _ => ThemeSound::File(path),
+                        });
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Finds a sound by name, falling back to less specific names ("a-b-c", "a-b", "a").
+    pub fn lookup(&self, name: &str) -> Option<ThemeSound> {
+        let mut name = name;
+        loop {
+            if let Some(sound) = self.lookup_exact(name) {
+                debug!("Found theme sound for \"{}\".", name);
+                return Some(sound);
+            }
+            match name.rfind('-') {
+                Some(i) => name = &name[..i],
+                None => return None,
+            }
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::io::prelude::*;
+use std::io::Cursor;
+use std::process;
+
+use log::debug;
+
+use crate::config::SpeechConfig;
+use crate::error::Error;
+use crate::sound::{self, Sound};
+
+/// Reads text out loud through an external text-to-speech command.
+pub struct Speaker {
+    command: Vec<String>,
+}
+
+impl Speaker {
+    pub fn new(config: &SpeechConfig) -> Option<Self> {
+        if config.enabled {
+            Some(Speaker {
+                command: config.command.clone(),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Speaks the text on the sink with the given index and blocks until it is done.
+    pub fn speak(&self, text: &str, sink_index: i32) -> Result<(), Error> {
+        let (program, args) = self
+            .command
+            .split_first()
+            .ok_or_else(|| Error::Usage(String::from("No speech command configured.")))?;
+        // Only a whole argument is replaced, so the text, e.g. a device name, always stays a
+        // single argument and never becomes part of a shell command line.
+        let args: Vec<String> = args
+            .iter()
+            .map(|a| {
+                if a == "{text}" {
+                    text.to_string()
+                } else {
+                    a.clone()
+                }
+            })
+            .collect();
+        debug!("Speaking \"{}\" with {} {:?}.", text, program, args);
+
+        let mut child = process::Command::new(program)
+            .args(&args)
+            .stdin(process::Stdio::piped())
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // Commands taking the text as argument might not read stdin at all.
+            stdin
+                .write_all(text.as_bytes())
+                .unwrap_or_else(|e| debug!("Could not write text to the speech command: {}", e));
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(Error::CommandFailed {
+                command: self.command.join(" "),
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        }
+
+        let sound = Sound::decode_wav(Cursor::new(output.stdout))?;
+        sound::play(sound, sink_index)
+    }
+}
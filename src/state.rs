@@ -0,0 +1,376 @@
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, prelude::*};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use log::error;
+
+use crate::error::Error;
+
+/// A command to run at a unix timestamp, with its arguments as given on the command line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScheduledCommand {
+    pub time: i64,
+    pub args: Vec<String>,
+}
+
+/// Separates the arguments of a scheduled command in the state file. Arguments cannot contain
+/// it, unlike spaces, e.g. in the description of a sink.
+pub const ARG_SEPARATOR: char = '\t';
+
+/// Path of a file in the runtime directory of the user.
+pub fn get_runtime_file_path(name: &str) -> Result<PathBuf, Error> {
+    let p = env::var("XDG_RUNTIME_DIR")?;
+    Ok(Path::new(&p).join(name))
+}
+
+fn get_data_file_path() -> Result<PathBuf, Error> {
+    get_runtime_file_path("audio_tools_notification_id")
+}
+
+/// The push-to-talk process holding a microphone open.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PushToTalk {
+    Live {
+        pid: u32,
+        source: String,
+    },
+    /// `ptt stop` came while nothing was live, at this time in milliseconds. When a key is only
+    /// tapped, the stop can overtake the start.
+    Released {
+        time: i64,
+    },
+}
+
+/// How to undo ducking a stream.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Restore {
+    Volumes(Vec<i32>),
+    Unmute,
+    /// Bus name of the paused media player.
+    Resume(String),
+    /// The stream belongs to a player that is resumed through another stream.
+    Nothing,
+}
+
+/// A stream lowered, muted or paused during a call. Kept in the state, so that a restarted
+/// daemon can still restore it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DuckedStream {
+    pub index: i32,
+    /// The sound server reuses indexes after restarting, the application tells streams apart.
+    pub application: String,
+    pub restore: Restore,
+}
+
+/// Everything kept between runs. Runs are short, so this is read and written each time.
+#[derive(Default)]
+struct State {
+    volume_id: Option<u32>,
+    sink_id: Option<u32>,
+    schedule: Vec<ScheduledCommand>,
+    push_to_talk: Option<PushToTalk>,
+    /// Bus names of the media players paused by muting.
+    paused_players: Vec<String>,
+    ducked_streams: Vec<DuckedStream>,
+}
+
+fn read_data_file() -> String {
+    let path = match get_data_file_path() {
+        Err(_) => {
+            println!("Unknown path to load from!");
+            return String::new();
+        }
+        Ok(p) => p,
+    };
+    let mut s = String::new();
+    match File::open(&path) {
+        Err(_) => println!("Could not open file at {:?}!", path),
+        Ok(mut file) => {
+            if file.read_to_string(&mut s).is_err() {
+                println!("Could not read file at {:?}!", path)
+            }
+        }
+    };
+    s
+}
+
+/// The first line holds the notification IDs, every further line a scheduled command ("<time>
+/// <arguments separated by tabs>"), the push-to-talk process ("ptt <pid> <source>" or
+/// "ptt released <time>"), a paused media player ("mpris <bus name>") or a ducked stream ("duck <index> volumes <v,v> <application>",
+/// "duck <index> resume <bus name> <application>", "duck <index> unmute|nothing <application>").
+fn read_state() -> State {
+    let data = read_data_file();
+    let mut lines = data.lines();
+    let mut ids = lines.next().unwrap_or("").split(';');
+    let mut state = State {
+        volume_id: ids.next().and_then(|id| id.parse::<u32>().ok()),
+        sink_id: ids.next().and_then(|id| id.parse::<u32>().ok()),
+        ..State::default()
+    };
+    for line in lines {
+        let mut parts = line.splitn(2, ' ');
+        match (parts.next(), parts.next()) {
+            (Some("ptt"), Some(rest)) => {
+                let mut parts = rest.splitn(2, ' ');
+                match (parts.next(), parts.next()) {
+                    (Some("released"), Some(time)) => match time.parse::<i64>() {
+                        Ok(time) => state.push_to_talk = Some(PushToTalk::Released { time }),
+                        Err(_) => error!("Invalid push-to-talk entry \"{}\".", line),
+                    },
+                    (Some(pid), Some(source)) => match pid.parse::<u32>() {
+                        Ok(pid) => {
+                            state.push_to_talk = Some(PushToTalk::Live {
+                                pid,
+                                source: source.to_string(),
+                            })
+                        }
+                        Err(_) => error!("Invalid push-to-talk entry \"{}\".", line),
+                    },
+                    _ => error!("Invalid push-to-talk entry \"{}\".", line),
+                }
+            }
+            (Some("mpris"), Some(bus_name)) => state.paused_players.push(bus_name.to_string()),
+            (Some("duck"), Some(rest)) => match parse_ducked_stream(rest) {
+                Some(ducked) => state.ducked_streams.push(ducked),
+                None => error!("Invalid ducked stream \"{}\".", line),
+            },
+            (Some(time), Some(command)) => match time.parse::<i64>() {
+                Ok(time) => state.schedule.push(ScheduledCommand {
+                    time,
+                    args: parse_scheduled_args(command),
+                }),
+                Err(_) => error!("Invalid scheduled command \"{}\".", line),
+            },
+            _ => error!("Invalid scheduled command \"{}\".", line),
+        }
+    }
+    state
+}
+
+/// Arguments of a scheduled command. Older entries are separated by spaces.
+fn parse_scheduled_args(command: &str) -> Vec<String> {
+    if command.contains(ARG_SEPARATOR) {
+        command.split(ARG_SEPARATOR).map(String::from).collect()
+    } else {
+        command.split_whitespace().map(String::from).collect()
+    }
+}
+
+fn parse_ducked_stream(line: &str) -> Option<DuckedStream> {
+    let mut parts = line.splitn(3, ' ');
+    let index = parts.next()?.parse::<i32>().ok()?;
+    let kind = parts.next()?;
+    let rest = parts.next().unwrap_or("");
+    let (restore, application) = match kind {
+        "volumes" | "resume" => {
+            let (value, application) = rest.split_once(' ').unwrap_or((rest, ""));
+            let restore = if kind == "volumes" {
+                Restore::Volumes(
+                    value
+                        .split(',')
+                        .map(|v| v.parse::<i32>().ok())
+                        .collect::<Option<Vec<i32>>>()?,
+                )
+            } else {
+                Restore::Resume(value.to_string())
+            };
+            (restore, application)
+        }
+        "unmute" => (Restore::Unmute, rest),
+        "nothing" => (Restore::Nothing, rest),
+        _ => return None,
+    };
+    Some(DuckedStream {
+        index,
+        application: application.to_string(),
+        restore,
+    })
+}
+
+/// Takes an exclusive lock on the state, held until the returned file is closed. The CLI, the
+/// threads of the daemon and push-to-talk all update the state, each has to see the changes of
+/// the others.
+fn lock_state() -> Result<File, Error> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(get_runtime_file_path("audio_tools_state.lock")?)?;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(file)
+}
+
+/// Writes to a temporary file first and renames it, so that readers never see a partial state.
+fn write_state(state: &State) -> Result<(), Error> {
+    let p = get_data_file_path()?;
+    let volume_id = match state.volume_id {
+        Some(id) => format!("{}", id),
+        None => "".to_string(),
+    };
+    let sink_id = match state.sink_id {
+        Some(id) => format!("{}", id),
+        None => "".to_string(),
+    };
+    let mut content = format!("{};{}", volume_id, sink_id);
+    for scheduled in &state.schedule {
+        content.push_str(&format!(
+            "\n{} {}",
+            scheduled.time,
+            scheduled.args.join(&ARG_SEPARATOR.to_string())
+        ));
+    }
+    match &state.push_to_talk {
+        Some(PushToTalk::Live { pid, source }) => {
+            content.push_str(&format!("\nptt {} {}", pid, source))
+        }
+        Some(PushToTalk::Released { time }) => {
+            content.push_str(&format!("\nptt released {}", time))
+        }
+        None => {}
+    }
+    for bus_name in &state.paused_players {
+        content.push_str(&format!("\nmpris {}", bus_name));
+    }
+    for ducked in &state.ducked_streams {
+        let restore = match &ducked.restore {
+            Restore::Volumes(volumes) => format!(
+                "volumes {}",
+                volumes
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ),
+            Restore::Unmute => String::from("unmute"),
+            Restore::Resume(bus_name) => format!("resume {}", bus_name),
+            Restore::Nothing => String::from("nothing"),
+        };
+        content.push_str(&format!(
+            "\nduck {} {} {}",
+            ducked.index, restore, ducked.application
+        ));
+    }
+    let temporary = p.with_extension("tmp");
+    File::create(&temporary)?.write_all(content.as_bytes())?;
+    fs::rename(temporary, p)?;
+    Ok(())
+}
+
+/// Reads, changes and writes the state while holding the lock.
+fn update_state<T>(update: impl FnOnce(&mut State) -> T) -> Result<T, Error> {
+    let _lock = lock_state()?;
+    let mut state = read_state();
+    let result = update(&mut state);
+    write_state(&state)?;
+    Ok(result)
+}
+
+pub fn load_data_from_file() -> Result<(Option<u32>, Option<u32>), Error> {
+    let state = read_state();
+    Ok((state.volume_id, state.sink_id))
+}
+
+pub fn write_data_to_file(volume_id: Option<u32>, sink_id: Option<u32>) -> Result<(), Error> {
+    update_state(|state| {
+        state.volume_id = volume_id;
+        state.sink_id = sink_id;
+    })
+}
+
+pub fn load_schedule() -> Vec<ScheduledCommand> {
+    read_state().schedule
+}
+
+/// Changes the schedule without losing the commands added by others in the meantime.
+pub fn update_schedule<T>(
+    update: impl FnOnce(&mut Vec<ScheduledCommand>) -> T,
+) -> Result<T, Error> {
+    update_state(|state| update(&mut state.schedule))
+}
+
+pub fn load_push_to_talk() -> Option<PushToTalk> {
+    read_state().push_to_talk
+}
+
+/// Changes the push-to-talk record while holding the lock, e.g. to only clear it if nobody else
+/// took over in the meantime.
+pub fn update_push_to_talk<T>(
+    update: impl FnOnce(&mut Option<PushToTalk>) -> T,
+) -> Result<T, Error> {
+    update_state(|state| update(&mut state.push_to_talk))
+}
+
+pub fn write_paused_players(paused_players: &[String]) -> Result<(), Error> {
+    update_state(|state| state.paused_players = paused_players.to_vec())
+}
+
+/// Removes the paused players from the state and returns them.
+pub fn take_paused_players() -> Result<Vec<String>, Error> {
+    update_state(|state| std::mem::take(&mut state.paused_players))
+}
+
+pub fn load_ducked_streams() -> Vec<DuckedStream> {
+    read_state().ducked_streams
+}
+
+pub fn write_ducked_streams(ducked_streams: &[DuckedStream]) -> Result<(), Error> {
+    update_state(|state| state.ducked_streams = ducked_streams.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ducked_streams() {
+        assert_eq!(
+            parse_ducked_stream("41 volumes 100,80 Firefox"),
+            Some(DuckedStream {
+                index: 41,
+                application: String::from("Firefox"),
+                restore: Restore::Volumes(vec![100, 80]),
+            })
+        );
+        assert_eq!(
+            parse_ducked_stream("7 resume org.mpris.MediaPlayer2.spotify Spotify Free"),
+            Some(DuckedStream {
+                index: 7,
+                application: String::from("Spotify Free"),
+                restore: Restore::Resume(String::from("org.mpris.MediaPlayer2.spotify")),
+            })
+        );
+        assert_eq!(
+            parse_ducked_stream("3 unmute mpv Media Player"),
+            Some(DuckedStream {
+                index: 3,
+                application: String::from("mpv Media Player"),
+                restore: Restore::Unmute,
+            })
+        );
+        assert_eq!(
+            parse_ducked_stream("3 nothing ").map(|d| d.restore),
+            Some(Restore::Nothing)
+        );
+        assert_eq!(parse_ducked_stream("3 volumes 100,x Firefox"), None);
+        assert_eq!(parse_ducked_stream("x unmute Firefox"), None);
+        assert_eq!(parse_ducked_stream("3 louder Firefox"), None);
+    }
+
+    #[test]
+    fn parses_scheduled_args() {
+        assert_eq!(
+            parse_scheduled_args("select_input\tBuilt-in Audio"),
+            vec!["select_input", "Built-in Audio"]
+        );
+        assert_eq!(parse_scheduled_args("mute"), vec!["mute"]);
+        // Written before the arguments were separated by tabs.
+        assert_eq!(
+            parse_scheduled_args("fade 20 --duration 5000"),
+            vec!["fade", "20", "--duration", "5000"]
+        );
+    }
+}
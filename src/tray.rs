@@ -0,0 +1,434 @@
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use dbus::tree::{Factory, MethodErr};
+use dbus::{BusType, Connection, Message, MessageItem, MessageItemArray, MessageType, Path};
+use log::{debug, error, info, warn};
+
+use crate::config::Config;
+use crate::daemon;
+use crate::error::Error;
+use crate::volume::VolumeInfo;
+use crate::volume_control::{follow_changes, Facility, Sink, VolumeControl};
+
+const ITEM_PATH: &str = "/StatusNotifierItem";
+const ITEM_INTERFACE: &str = "org.kde.StatusNotifierItem";
+const MENU_PATH: &str = "/MenuBar";
+const MENU_INTERFACE: &str = "com.canonical.dbusmenu";
+const WATCHER: &str = "org.kde.StatusNotifierWatcher";
+const TIMEOUT_MS: i32 = 500;
+/// How often the volume is read again while the sound server does not answer.
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Icon name, pixmaps, title and description.
+type ToolTip = (String, Vec<(i32, i32, Vec<u8>)>, String, String);
+
+/// What the icon and the menu show.
+#[derive(Default)]
+struct TrayState {
+    volume: Option<VolumeInfo>,
+    sinks: Vec<Sink>,
+    /// Counts the changes of the menu, so that the tray asks for it again.
+    revision: u32,
+}
+
+impl TrayState {
+    fn icon_name(&self) -> &'static str {
+        match &self.volume {
+            Some(volume) if !volume.muted && volume.volume > 66 => "audio-volume-high",
+            Some(volume) if !volume.muted && volume.volume > 33 => "audio-volume-medium",
+            Some(volume) if !volume.muted && volume.volume > 0 => "audio-volume-low",
+            _ => "audio-volume-muted",
+        }
+    }
+
+    fn tool_tip(&self) -> ToolTip {
+        let title = match &self.volume {
+            Some(volume) if volume.muted => format!("Volume {}% (muted)", volume.volume),
+            Some(volume) => format!("Volume {}%", volume.volume),
+            None => String::from("Volume"),
+        };
+        let active_sink = self
+            .sinks
+            .iter()
+            .find(|s| s.active)
+            .map(|s| s.name.clone())
+            .unwrap_or_default();
+        (self.icon_name().to_string(), Vec::new(), title, active_sink)
+    }
+
+    /// Reads the volume and the sinks again. Returns whether the icon and whether the menu
+    /// changed.
+    fn refresh(&mut self, volume_control: &mut VolumeControl) -> Result<(bool, bool), Error> {
+        let volume = Some(VolumeInfo::get_volume()?);
+        let sinks = volume_control.get_available_interfaces()?.clone();
+        let icon_changed = volume != self.volume || sinks != self.sinks;
+        // The menu only shows the names and which sink is active.
+        let menu_changed = sinks.len() != self.sinks.len()
+            || sinks
+                .iter()
+                .zip(&self.sinks)
+                .any(|(a, b)| a.index != b.index || a.name != b.name || a.active != b.active);
+        self.volume = volume;
+        self.sinks = sinks;
+        if menu_changed {
+            self.revision += 1;
+        }
+        Ok((icon_changed, menu_changed))
+    }
+
+    /// Menu item of a sink. IDs start at 1, 0 is the root of the menu.
+    fn sink_item(&self, sink: &Sink) -> Result<MessageItem, MethodErr> {
+        menu_item(
+            sink.index + 1,
+            vec![
+                ("label", MessageItem::Str(sink.name.clone())),
+                ("toggle-type", MessageItem::Str(String::from("radio"))),
+                ("toggle-state", MessageItem::Int32(sink.active as i32)),
+            ],
+            Vec::new(),
+        )
+    }
+
+    fn find_sink(&self, id: i32) -> Option<&Sink> {
+        self.sinks.iter().find(|s| s.index + 1 == id)
+    }
+
+    /// The menu below the item with the given ID, in the (ia{sv}av) layout of dbusmenu.
+    fn layout(&self, id: i32) -> Result<MessageItem, MethodErr> {
+        if let Some(sink) = self.find_sink(id) {
+            return self.sink_item(sink);
+        }
+        let children = self
+            .sinks
+            .iter()
+            .map(|s| Ok(MessageItem::Variant(Box::new(self.sink_item(s)?))))
+            .collect::<Result<Vec<MessageItem>, MethodErr>>()?;
+        menu_item(
+            0,
+            vec![(
+                "children-display",
+                MessageItem::Str(String::from("submenu")),
+            )],
+            children,
+        )
+    }
+}
+
+fn menu_item(
+    id: i32,
+    properties: Vec<(&str, MessageItem)>,
+    children: Vec<MessageItem>,
+) -> Result<MessageItem, MethodErr> {
+    let properties = MessageItem::from_dict(
+        properties
+            .into_iter()
+            .map(|(k, v)| Ok::<_, MethodErr>((k.to_string(), v))),
+    )?;
+    let children = MessageItemArray::new(children, "av".into())
+        .map_err(|e| MethodErr::failed(&format!("{:?}", e)))?;
+    Ok(MessageItem::Struct(vec![
+        MessageItem::Int32(id),
+        properties,
+        MessageItem::Array(children),
+    ]))
+}
+
+/// Runs a command like the hotkeys do, without blocking the tray.
+fn run_command(config: &Arc<Config>, command: String) {
+    let config = config.clone();
+    thread::spawn(move || {
+        daemon::execute(&config, &command)
+            .unwrap_or_else(|e| error!("Tray command \"{}\" failed: {}", command, e));
+    });
+}
+
+/// Tells the tray about the item. Trays started later pick it up through NameOwnerChanged.
+fn register(connection: &Connection, name: &str) {
+    let message = match Message::new_method_call(
+        WATCHER,
+        "/StatusNotifierWatcher",
+        WATCHER,
+        "RegisterStatusNotifierItem",
+    ) {
+        Ok(message) => message.append1(name),
+        Err(e) => return error!("Failed to register the tray icon: {}", e),
+    };
+    match connection.send_with_reply_and_block(message, TIMEOUT_MS) {
+        Ok(_) => info!("Tray icon registered as {}.", name),
+        Err(e) => warn!("No tray to show the icon in yet: {}", e),
+    }
+}
+
+/// Whether a message tells that a tray started.
+fn is_new_watcher(message: &Message) -> bool {
+    if message.msg_type() != MessageType::Signal
+        || message.member().as_deref() != Some("NameOwnerChanged")
+    {
+        return false;
+    }
+    let (name, _, new_owner) = message.get3::<&str, &str, &str>();
+    name == Some(WATCHER) && new_owner.is_some_and(|owner| !owner.is_empty())
+}
+
+/// Waits for the sound server, which may still be starting along with the session.
+fn wait_for_volume_control() -> VolumeControl {
+    let mut logged = false;
+    loop {
+        match VolumeControl::new() {
+            Ok(volume_control) => return volume_control,
+            Err(e) if !logged => {
+                error!("Tray icon is waiting for the sound server: {}", e);
+                logged = true;
+            }
+            Err(_) => {}
+        }
+        thread::sleep(RETRY_INTERVAL);
+    }
+}
+
+fn serve(config: Arc<Config>) -> Result<(), Error> {
+    let mut volume_control = wait_for_volume_control();
+    let state = Arc::new(Mutex::new(TrayState::default()));
+    // Read again on every turn of the loop until the sound server answers, e.g. while it
+    // restarts.
+    let mut stale = match state.lock().unwrap().refresh(&mut volume_control) {
+        Ok(_) => false,
+        Err(e) => {
+            error!("Failed to read the volume for the tray: {}", e);
+            true
+        }
+    };
+
+    let f = Factory::new_fn::<()>();
+    let item = {
+        let (icon_state, tool_tip_state) = (state.clone(), state.clone());
+        let (activate_config, mute_config, scroll_config) =
+            (config.clone(), config.clone(), config.clone());
+        f.interface(ITEM_INTERFACE, ())
+            .add_p(f.property::<&str, _>("Category", ()).on_get(|i, _| {
+                i.append("Hardware");
+                Ok(())
+            }))
+            .add_p(f.property::<&str, _>("Id", ()).on_get(|i, _| {
+                i.append("audio_tools");
+                Ok(())
+            }))
+            .add_p(f.property::<&str, _>("Title", ()).on_get(|i, _| {
+                i.append("Volume");
+                Ok(())
+            }))
+            .add_p(f.property::<&str, _>("Status", ()).on_get(|i, _| {
+                i.append("Active");
+                Ok(())
+            }))
+            .add_p(f.property::<&str, _>("IconName", ()).on_get(move |i, _| {
+                i.append(icon_state.lock().unwrap().icon_name());
+                Ok(())
+            }))
+            .add_p(f.property::<ToolTip, _>("ToolTip", ()).on_get(move |i, _| {
+                i.append(tool_tip_state.lock().unwrap().tool_tip());
+                Ok(())
+            }))
+            .add_p(f.property::<bool, _>("ItemIsMenu", ()).on_get(|i, _| {
+                i.append(false);
+                Ok(())
+            }))
+            .add_p(f.property::<Path, _>("Menu", ()).on_get(|i, _| {
+                i.append(Path::from(MENU_PATH));
+                Ok(())
+            }))
+            .add_m(
+                f.method("Activate", (), move |m| {
+                    run_command(&activate_config, String::from("volume_notification"));
+                    Ok(vec![m.msg.method_return()])
+                })
+                .inarg::<i32, _>("x")
+                .inarg::<i32, _>("y"),
+            )
+            .add_m(
+                f.method("SecondaryActivate", (), move |m| {
+                    run_command(&mute_config, String::from("mute"));
+                    Ok(vec![m.msg.method_return()])
+                })
+                .inarg::<i32, _>("x")
+                .inarg::<i32, _>("y"),
+            )
+            .add_m(
+                f.method("Scroll", (), move |m| {
+                    let (delta, orientation): (i32, &str) = m.msg.read2()?;
+                    if orientation.eq_ignore_ascii_case("vertical") && delta != 0 {
+                        let command = if delta > 0 { "raise" } else { "lower" };
+                        run_command(&scroll_config, String::from(command));
+                    }
+                    Ok(vec![m.msg.method_return()])
+                })
+                .inarg::<i32, _>("delta")
+                .inarg::<&str, _>("orientation"),
+            )
+            .add_m(
+                f.method("ContextMenu", (), |m| Ok(vec![m.msg.method_return()]))
+                    .inarg::<i32, _>("x")
+                    .inarg::<i32, _>("y"),
+            )
+            .add_s(f.signal("NewIcon", ()))
+            .add_s(f.signal("NewToolTip", ()))
+    };
+    let menu = {
+        let (layout_state, group_state, event_state) =
+            (state.clone(), state.clone(), state.clone());
+        let config = config.clone();
+        f.interface(MENU_INTERFACE, ())
+            .add_p(f.property::<u32, _>("Version", ()).on_get(|i, _| {
+                i.append(3u32);
+                Ok(())
+            }))
+            .add_p(f.property::<&str, _>("TextDirection", ()).on_get(|i, _| {
+                i.append("ltr");
+                Ok(())
+            }))
+            .add_p(f.property::<&str, _>("Status", ()).on_get(|i, _| {
+                i.append("normal");
+                Ok(())
+            }))
+            .add_m(
+                f.method("GetLayout", (), move |m| {
+                    let parent: i32 = m.msg.read1()?;
+                    let state = layout_state.lock().unwrap();
+                    let layout = state.layout(parent)?;
+                    Ok(vec![m.msg.method_return().append2(state.revision, layout)])
+                })
+                .inarg::<i32, _>("parentId")
+                .inarg::<i32, _>("recursionDepth")
+                .inarg::<Vec<&str>, _>("propertyNames")
+                .out_arg(("revision", "u"))
+                .out_arg(("layout", "(ia{sv}av)")),
+            )
+            .add_m(
+                f.method("GetGroupProperties", (), move |m| {
+                    let ids: Vec<i32> = m.msg.read1()?;
+                    let state = group_state.lock().unwrap();
+                    let items = state
+                        .sinks
+                        .iter()
+                        .filter(|s| ids.is_empty() || ids.contains(&(s.index + 1)))
+                        .map(|s| match state.sink_item(s)? {
+                            // Only the ID and the properties, without the children.
+                            MessageItem::Struct(mut fields) => {
+                                fields.truncate(2);
+                                Ok(MessageItem::Struct(fields))
+                            }
+                            item => Ok(item),
+                        })
+                        .collect::<Result<Vec<MessageItem>, MethodErr>>()?;
+                    let items = MessageItemArray::new(items, "a(ia{sv})".into())
+                        .map_err(|e| MethodErr::failed(&format!("{:?}", e)))?;
+                    Ok(vec![m
+                        .msg
+                        .method_return()
+                        .append1(MessageItem::Array(items))])
+                })
+                .inarg::<Vec<i32>, _>("ids")
+                .inarg::<Vec<&str>, _>("propertyNames")
+                .out_arg(("properties", "a(ia{sv})")),
+            )
+            .add_m(
+                f.method("Event", (), move |m| {
+                    let (id, event): (i32, &str) = m.msg.read2()?;
+                    if event == "clicked" {
+                        if let Some(sink) = event_state.lock().unwrap().find_sink(id) {
+                            run_command(&config, format!("select_input {}", sink.sink_name));
+                        }
+                    }
+                    Ok(vec![m.msg.method_return()])
+                })
+                .inarg::<i32, _>("id")
+                .inarg::<&str, _>("eventId")
+                .in_arg(("data", "v"))
+                .inarg::<u32, _>("timestamp"),
+            )
+            .add_m(
+                f.method("AboutToShow", (), |m| {
+                    Ok(vec![m.msg.method_return().append1(false)])
+                })
+                .inarg::<i32, _>("id")
+                .outarg::<bool, _>("needUpdate"),
+            )
+            .add_s(f.signal("LayoutUpdated", ()))
+    };
+    let tree = f
+        .tree(())
+        .add(f.object_path(ITEM_PATH, ()).introspectable().add(item))
+        .add(f.object_path(MENU_PATH, ()).introspectable().add(menu));
+
+    let connection = Connection::get_private(BusType::Session)?;
+    let name = format!("org.kde.StatusNotifierItem-{}-1", process::id());
+    connection.register_name(&name, 0)?;
+    tree.set_registered(&connection, true)?;
+    connection.add_handler(tree);
+    connection.add_match(&format!(
+        "type='signal',interface='org.freedesktop.DBus',member='NameOwnerChanged',arg0='{}'",
+        WATCHER
+    ))?;
+    register(&connection, &name);
+
+    let changes = follow_changes(&[Facility::Sink, Facility::Server]);
+    loop {
+        for item in connection.incoming(1000) {
+            if is_new_watcher(&item) {
+                register(&connection, &name);
+            }
+        }
+        if changes.try_recv().is_err() && !stale {
+            continue;
+        }
+        // A single change often comes as several events.
+        while changes.try_recv().is_ok() {}
+        let refreshed = state.lock().unwrap().refresh(&mut volume_control);
+        let (icon_changed, menu_changed) = match refreshed {
+            Ok(changed) => changed,
+            Err(e) => {
+                if !stale {
+                    error!("Failed to read the volume for the tray: {}", e);
+                }
+                stale = true;
+                continue;
+            }
+        };
+        stale = false;
+        if icon_changed {
+            debug!("Updating the tray icon.");
+            for signal in &["NewIcon", "NewToolTip"] {
+                let message =
+                    Message::signal(&ITEM_PATH.into(), &ITEM_INTERFACE.into(), &(*signal).into());
+                connection
+                    .send(message)
+                    .map_err(|()| Error::Dbus(format!("Failed to send {} to the tray.", signal)))?;
+            }
+        }
+        if menu_changed {
+            let revision = state.lock().unwrap().revision;
+            let message = Message::signal(
+                &MENU_PATH.into(),
+                &MENU_INTERFACE.into(),
+                &"LayoutUpdated".into(),
+            )
+            .append2(revision, 0i32);
+            connection
+                .send(message)
+                .map_err(|()| Error::Dbus(String::from("Failed to send the menu to the tray.")))?;
+        }
+    }
+}
+
+/// Shows the volume as an icon in the tray through the StatusNotifierItem protocol of KDE, which
+/// most other trays speak as well, e.g. waybar or GNOME with the AppIndicator extension.
+/// Scrolling on the icon changes the volume, a middle click mutes and the menu switches the sink.
+pub fn run(config: Arc<Config>) {
+    info!("Showing the tray icon.");
+    if let Err(e) = serve(config) {
+        error!("Tray icon stopped: {}", e);
+    }
+}
@@ -0,0 +1,430 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crossterm::event::{
+    self, Event as TerminalEvent, KeyCode, KeyEvent, KeyEventKind, KeyModifiers,
+};
+use crossterm::style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor};
+use crossterm::terminal::{self, ClearType};
+use crossterm::{cursor, execute, queue};
+use log::debug;
+
+use crate::config::VolumeConfig;
+use crate::error::Error;
+use crate::notification::build_level_blocks;
+use crate::volume::{cap_volume_change, MAX_VOLUME};
+use crate::volume_control::{
+    follow_changes, list_sink_inputs, list_sources, Facility, Sink, VolumeControl,
+};
+
+const HELP: &str = "↑↓ select  ←→ volume  m mute  d default  s move stream  q quit";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Sink,
+    Source,
+    Stream,
+}
+
+struct Row {
+    kind: Kind,
+    index: i32,
+    label: String,
+    volumes: Vec<i32>,
+    muted: bool,
+    /// The default sink or source.
+    default: bool,
+    /// Index of the sink a stream plays on.
+    sink: i32,
+}
+
+impl Row {
+    fn volume(&self) -> i32 {
+        self.volumes.iter().copied().max().unwrap_or(0)
+    }
+}
+
+/// Restores the terminal when the mixer ends, also on errors.
+struct Screen;
+
+impl Screen {
+    fn enter() -> Result<Self, Error> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(Screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen).unwrap_or(());
+        terminal::disable_raw_mode().unwrap_or(());
+    }
+}
+
+/// Keeps the volume ratio between the channels. Raising stops at the cap, if there is one.
+fn changed_volumes(volumes: &[i32], amount: i32, max_volume: Option<i32>) -> Vec<i32> {
+    let max = volumes.iter().copied().max().unwrap_or(0);
+    let target = (max + cap_volume_change(max, amount, max_volume)).clamp(0, MAX_VOLUME);
+    volumes
+        .iter()
+        .map(|v| if max == 0 { target } else { v * target / max })
+        .collect()
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        format!("{:<width$}", text, width = width)
+    } else {
+        let mut truncated: String = text.chars().take(width.saturating_sub(1)).collect();
+        truncated.push('…');
+        truncated
+    }
+}
+
+struct Mixer<'a> {
+    volume_control: &'a mut VolumeControl,
+    /// The profiles cap the volume of the sinks.
+    volume_config: &'a VolumeConfig,
+    step: i32,
+    sinks: Vec<Sink>,
+    rows: Vec<Row>,
+    selected: usize,
+    status: Option<String>,
+}
+
+impl<'a> Mixer<'a> {
+    /// Lists the sinks, the sources without the monitors and the streams, keeping the selection.
+    fn reload(&mut self) -> Result<(), Error> {
+        let selection = self.rows.get(self.selected).map(|r| (r.kind, r.index));
+        self.sinks = self.volume_control.get_available_interfaces()?.clone();
+        let mut rows: Vec<Row> = self
+            .sinks
+            .iter()
+            .map(|s| Row {
+                kind: Kind::Sink,
+                index: s.index,
+                label: s.name.clone(),
+                volumes: s.volumes.clone(),
+                muted: s.muted,
+                default: s.active,
+                sink: s.index,
+            })
+            .collect();
+        rows.extend(
+            list_sources()?
+                .into_iter()
+                .filter(|s| s.monitor_of.is_none())
+                .map(|s| Row {
+                    kind: Kind::Source,
+                    index: s.index,
+                    label: s.name,
+                    volumes: s.volumes,
+                    muted: s.muted,
+                    default: s.active,
+                    sink: -1,
+                }),
+        );
+        for stream in list_sink_inputs()? {
+            let sink = match self.sinks.iter().find(|s| s.index == stream.sink) {
+                Some(sink) => sink.name.as_str(),
+                None => "?",
+            };
+            rows.push(Row {
+                kind: Kind::Stream,
+                index: stream.index,
+                label: format!("{} → {}", stream.application, sink),
+                volumes: stream.volumes,
+                muted: stream.muted,
+                default: false,
+                sink: stream.sink,
+            });
+        }
+        self.rows = rows;
+        self.selected = selection
+            .and_then(|(kind, index)| {
+                self.rows
+                    .iter()
+                    .position(|r| r.kind == kind && r.index == index)
+            })
+            .unwrap_or(self.selected)
+            .min(self.rows.len().saturating_sub(1));
+        Ok(())
+    }
+
+    fn change_volume(&mut self, amount: i32) -> Result<(), Error> {
+        let row = match self.rows.get(self.selected) {
+            Some(row) => row,
+            None => return Ok(()),
+        };
+        match row.kind {
+            Kind::Sink => {
+                let max_volume = self.volume_config.get_current_max_volume();
+                self.volume_control.set_sink_volumes(
+                    &row.index.to_string(),
+                    &changed_volumes(&row.volumes, amount, max_volume),
+                )
+            }
+            Kind::Source => self.volume_control.set_source_volumes(
+                &row.index.to_string(),
+                &changed_volumes(&row.volumes, amount, None),
+            ),
+            Kind::Stream => self
+                .volume_control
+                .set_sink_input_volumes(row.index, &changed_volumes(&row.volumes, amount, None)),
+        }
+    }
+
+    fn toggle_mute(&mut self) -> Result<(), Error> {
+        let row = match self.rows.get(self.selected) {
+            Some(row) => row,
+            None => return Ok(()),
+        };
+        match row.kind {
+            Kind::Sink => self
+                .volume_control
+                .set_sink_mute(&row.index.to_string(), !row.muted),
+            Kind::Source => self
+                .volume_control
+                .set_source_mute(&row.index.to_string(), !row.muted),
+            Kind::Stream => self
+                .volume_control
+                .set_sink_input_mute(row.index, !row.muted),
+        }
+    }
+
+    /// Makes a sink or source the default one, or moves a stream to the next sink.
+    fn make_default(&mut self) -> Result<(), Error> {
+        let row = match self.rows.get(self.selected) {
+            Some(row) => row,
+            None => return Ok(()),
+        };
+        match row.kind {
+            Kind::Sink => {
+                let index = row.index.to_string();
+                self.volume_control.set_default_sink(&index)
+            }
+            Kind::Source => self
+                .volume_control
+                .set_default_source(&row.index.to_string()),
+            Kind::Stream => self.move_stream(),
+        }
+    }
+
+    fn move_stream(&mut self) -> Result<(), Error> {
+        let row = match self.rows.get(self.selected) {
+            Some(row) if row.kind == Kind::Stream => row,
+            _ => return Ok(()),
+        };
+        if self.sinks.len() <= 1 {
+            return Err(Error::Unavailable(String::from(
+                "There is no other sink to move to.",
+            )));
+        }
+        let current = self.sinks.iter().position(|s| s.index == row.sink);
+        let next = &self.sinks[current.map_or(0, |i| (i + 1) % self.sinks.len())];
+        debug!("Moving stream {} to {}.", row.index, next.sink_name);
+        self.volume_control
+            .move_sink_input(row.index, &next.sink_name)
+    }
+
+    /// Returns false to quit.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let result = match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.selected = self.selected.saturating_sub(1);
+                return true;
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected = (self.selected + 1).min(self.rows.len().saturating_sub(1));
+                return true;
+            }
+            KeyCode::Left | KeyCode::Char('h') | KeyCode::Char('-') => {
+                self.change_volume(-self.step)
+            }
+            KeyCode::Right | KeyCode::Char('l') | KeyCode::Char('+') => {
+                self.change_volume(self.step)
+            }
+            KeyCode::Char('m') => self.toggle_mute(),
+            KeyCode::Char('d') | KeyCode::Enter => self.make_default(),
+            KeyCode::Char('s') => self.move_stream(),
+            _ => return true,
+        };
+        // The server reports the change, which reloads the rows.
+        self.status = result.err().map(|e| e.to_string());
+        true
+    }
+
+    fn draw(&self) -> Result<(), Error> {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, height as usize);
+        let label_width = width.saturating_sub(33).max(8);
+
+        // Section titles and rows, with the index of the row.
+        let mut lines: Vec<(Option<usize>, String)> = Vec::new();
+        for (kind, title) in [
+            (Kind::Sink, "Output devices"),
+            (Kind::Source, "Input devices"),
+            (Kind::Stream, "Playback streams"),
+        ] {
+            lines.push((None, String::from(title)));
+            lines.extend(
+                self.rows
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, r)| r.kind == kind)
+                    .map(|(i, _)| (Some(i), String::new())),
+            );
+        }
+
+        // Keep the selection in view, below the title and above the status and help.
+        let visible = height.saturating_sub(3).max(1);
+        let selected_line = lines
+            .iter()
+            .position(|(i, _)| *i == Some(self.selected))
+            .unwrap_or(0);
+        let first_line = (selected_line + 1).saturating_sub(visible);
+
+        let mut stdout = io::stdout();
+        queue!(
+            stdout,
+            terminal::Clear(ClearType::All),
+            cursor::MoveTo(0, 0),
+            SetAttribute(Attribute::Bold),
+            Print("audio_tools mixer"),
+            SetAttribute(Attribute::Reset),
+        )?;
+        for (line_number, (row_index, title)) in
+            lines.iter().enumerate().skip(first_line).take(visible)
+        {
+            queue!(
+                stdout,
+                cursor::MoveTo(0, (line_number - first_line + 1) as u16)
+            )?;
+            let row = match row_index {
+                Some(i) => &self.rows[*i],
+                None => {
+                    queue!(
+                        stdout,
+                        SetAttribute(Attribute::Bold),
+                        Print(title),
+                        SetAttribute(Attribute::Reset)
+                    )?;
+                    continue;
+                }
+            };
+            let (full_blocks, empty_blocks) = build_level_blocks(row.volume().min(100), row.muted);
+            if *row_index == Some(self.selected) {
+                queue!(stdout, SetAttribute(Attribute::Reverse))?;
+            }
+            queue!(
+                stdout,
+                Print(format!(
+                    " {} {} ",
+                    if row.default { "→" } else { " " },
+                    truncate(&row.label, label_width)
+                )),
+                Print(full_blocks),
+                SetForegroundColor(Color::DarkGrey),
+                Print(empty_blocks.replace('█', "░")),
+                ResetColor,
+                Print(format!(
+                    " {:>3}%{}",
+                    row.volume(),
+                    if row.muted { " muted" } else { "" }
+                )),
+                SetAttribute(Attribute::Reset),
+            )?;
+        }
+        let bottom = height.saturating_sub(1) as u16;
+        if let Some(status) = &self.status {
+            queue!(
+                stdout,
+                cursor::MoveTo(0, bottom.saturating_sub(1)),
+                SetForegroundColor(Color::Red),
+                Print(truncate(status, width)),
+                ResetColor
+            )?;
+        }
+        queue!(
+            stdout,
+            cursor::MoveTo(0, bottom),
+            Print(truncate(HELP, width))
+        )?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+/// Full-screen mixer of the sinks, sources and streams, which updates on every change on the
+/// sound server. Volume keys change the volume by `step` percent, sinks up to the cap of the active
+/// volume profile.
+pub fn run(
+    volume_control: &mut VolumeControl,
+    volume_config: &VolumeConfig,
+    step: i32,
+) -> Result<(), Error> {
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+    let mut mixer = Mixer {
+        volume_control,
+        volume_config,
+        step,
+        sinks: Vec::new(),
+        rows: Vec::new(),
+        selected: 0,
+        status: None,
+    };
+    mixer.reload()?;
+
+    let changes = follow_changes(&[
+        Facility::Sink,
+        Facility::Source,
+        Facility::SinkInput,
+        Facility::Server,
+    ]);
+
+    let _screen = Screen::enter()?;
+    let mut redraw = true;
+    loop {
+        if redraw {
+            mixer.draw()?;
+            redraw = false;
+        }
+        if event::poll(POLL_INTERVAL)? {
+            match event::read()? {
+                TerminalEvent::Key(key) if key.kind == KeyEventKind::Press => {
+                    if !mixer.handle_key(key) {
+                        return Ok(());
+                    }
+                    redraw = true;
+                }
+                TerminalEvent::Resize(_, _) => redraw = true,
+                _ => {}
+            }
+        }
+        // A single change often comes as several events.
+        if changes.try_iter().count() > 0 {
+            if let Err(e) = mixer.reload() {
+                mixer.status = Some(e.to_string());
+            }
+            redraw = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_volumes_within_the_cap() {
+        assert_eq!(changed_volumes(&[40, 20], 10, None), vec![50, 25]);
+        assert_eq!(changed_volumes(&[40, 20], 10, Some(45)), vec![45, 22]);
+        assert_eq!(changed_volumes(&[60, 60], 5, Some(45)), vec![60, 60]);
+        assert_eq!(changed_volumes(&[60, 60], -5, Some(45)), vec![55, 55]);
+        assert_eq!(changed_volumes(&[148], 5, None), vec![MAX_VOLUME]);
+        assert_eq!(changed_volumes(&[0, 0], 5, None), vec![5, 5]);
+    }
+}
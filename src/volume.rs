@@ -1,37 +1,343 @@
-use std::error::Error;
-use std::process;
-use std::str;
-
+use log::{debug, info};
 use regex::Regex;
 
+use crate::config::StepMode;
+use crate::error::Error;
+use crate::volume_control::run_backend;
+
+/// Highest volume in percent the mixer and fades go to, like in pavucontrol.
+pub(crate) const MAX_VOLUME: i32 = 150;
+
+/// Limits raising the volume from `volume` by `amount` to the cap of the active profile. Lowering
+/// is never limited, and a volume above the cap is not raised any further.
+pub(crate) fn cap_volume_change(volume: i32, amount: i32, max_volume: Option<i32>) -> i32 {
+    match max_volume {
+        Some(max_volume) if amount > 0 => {
+            let capped = amount.min(max_volume - volume).max(0);
+            if capped < amount {
+                info!("Volume is capped at {}%.", max_volume);
+            }
+            capped
+        }
+        _ => amount,
+    }
+}
+
+/// Lowers all channels alike, keeping the balance, so that none is above the cap of the active
+/// profile.
+pub(crate) fn cap_channel_volumes(volumes: &[i32], max_volume: Option<i32>) -> Vec<i32> {
+    let loudest = volumes.iter().copied().max().unwrap_or(0);
+    match max_volume {
+        Some(max_volume) if loudest > max_volume => {
+            debug!("Volume is capped at {}%.", max_volume);
+            volumes.iter().map(|v| v * max_volume / loudest).collect()
+        }
+        _ => volumes.to_vec(),
+    }
+}
+
+/// Lowers a volume, e.g. the target of a fade, to the cap of the active profile.
+pub(crate) fn cap_volume(volume: i32, max_volume: Option<i32>) -> i32 {
+    cap_channel_volumes(&[volume], max_volume)[0]
+}
+
+/// The volume of the sound server is on a cubic scale, like in pavucontrol.
+pub fn percent_to_db(percent: f32) -> Option<f32> {
+    if percent <= 0.0 {
+        None
+    } else {
+        Some(60.0 * (percent / 100.0).log10())
+    }
+}
+
+pub fn db_to_percent(db: f32) -> f32 {
+    100.0 * 10f32.powf(db / 60.0)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct ChannelVolume {
+    pub name: String,
+    pub volume: i32,
+    pub db: Option<f32>,
+}
+
+impl ChannelVolume {
+    fn is_left(&self) -> bool {
+        self.name.contains("Left")
+    }
+
+    fn is_right(&self) -> bool {
+        self.name.contains("Right")
+    }
+
+    /// Short name for display, e.g. "FL" for "Front Left".
+    pub fn short_name(&self) -> String {
+        self.name
+            .split_whitespace()
+            .filter_map(|w| w.chars().next())
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BalanceDirection {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub struct VolumeInfo {
+    /// Loudest channel.
     pub volume: i32,
+    /// Loudest channel in dB, if there is any signal.
+    pub db: Option<f32>,
     pub muted: bool,
+    pub channels: Vec<ChannelVolume>,
+    /// From -1 (only left) over 0 (centered) to 1 (only right).
+    pub balance: f32,
+}
+
+pub(crate) const GET_VOLUME_ARGS: [&str; 4] = ["-D", "pulse", "sget", "Master"];
+
+fn average(volumes: &[i32]) -> Option<f32> {
+    if volumes.is_empty() {
+        None
+    } else {
+        Some(volumes.iter().sum::<i32>() as f32 / volumes.len() as f32)
+    }
 }
 
 impl VolumeInfo {
-    pub fn get_volume() -> Result<Self, Box<dyn Error>> {
-        let volume = process::Command::new("amixer")
-            .args(&["-D", "pulse", "sget", "Master"])
-            .output()?;
-        if !volume.status.success() {
-            eprintln!("{}", str::from_utf8(&volume.stderr).unwrap());
+    pub fn get_volume() -> Result<Self, Error> {
+        VolumeInfo::parse(&run_backend("amixer", &GET_VOLUME_ARGS)?)
+    }
+
+    /// Parses the output of `amixer sget Master`.
+    pub(crate) fn parse(amixer_output: &str) -> Result<Self, Error> {
+        let re = Regex::new(
+            r"^\s*(?P<name>[A-Za-z ]+): Playback [0-9]+ \[(?P<volume>[0-9]+)%\](?: \[(?P<db>-?[0-9.]+|-inf)dB\])?.* \[(?P<switch>[A-Za-z]+)\]",
+        )?;
+        let mut channels = Vec::new();
+        let mut muted = None;
+        for (line, cap) in amixer_output
+            .lines()
+            .filter_map(|l| re.captures(l).map(|c| (l, c)))
+        {
+            let channel_muted = match &cap["switch"] {
+                "on" => false,
+                "off" => true,
+                &_ => return Err(Error::parse(line, "Unknown mute status.")),
+            };
+            muted = Some(muted.unwrap_or(false) || channel_muted);
+            let volume: i32 = cap["volume"].parse().map_err(|e| Error::parse(line, e))?;
+            // Hardware mixers report dB themselves, otherwise use the scale of the server.
+            let db = match cap.name("db") {
+                Some(db) => db.as_str().parse::<f32>().ok().filter(|db| db.is_finite()),
+                None => percent_to_db(volume as f32),
+            };
+            channels.push(ChannelVolume {
+                name: cap["name"].to_string(),
+                volume,
+                db,
+            });
+        }
+
+        match (channels.iter().max_by_key(|c| c.volume), muted) {
+            (Some(loudest), Some(muted)) => {
+                let volume = loudest.volume;
+                let db = loudest.db;
+                let balance = VolumeInfo::compute_balance(&channels);
+                Ok(Self {
+                    volume,
+                    db,
+                    muted,
+                    channels,
+                    balance,
+                })
+            }
+            _ => Err(Error::parse(
+                amixer_output.lines().next().unwrap_or(""),
+                "No playback channels in the output of amixer.",
+            )),
         }
-        let amixer_output = str::from_utf8(&volume.stdout)?;
-        let re = Regex::new(r"\[([0-9]+)%\] \[([A-Za-z]+)\]")?;
-        for cap in re.captures_iter(amixer_output) {
-            if cap.len() == 3 {
-                let volume: i32 = cap[1].parse()?;
-                let muted = match &cap[2] {
-                    "on" => false,
-                    "off" => true,
-                    &_ => return Err(String::from("Error parsing mute status.").into()),
-                };
-
-                return Ok(Self { volume, muted });
+    }
+
+    /// Same as PulseAudio, the balance is the ratio of the average right and left volumes.
+    fn compute_balance(channels: &[ChannelVolume]) -> f32 {
+        let left = average(
+            &channels
+                .iter()
+                .filter(|c| c.is_left())
+                .map(|c| c.volume)
+                .collect::<Vec<i32>>(),
+        );
+        let right = average(
+            &channels
+                .iter()
+                .filter(|c| c.is_right())
+                .map(|c| c.volume)
+                .collect::<Vec<i32>>(),
+        );
+        match (left, right) {
+            (Some(left), Some(right)) if left > right => -1.0 + right / left,
+            (Some(left), Some(right)) if right > left => 1.0 - left / right,
+            _ => 0.0,
+        }
+    }
+
+    /// Channel volumes keeping the loudest volume but with the given balance.
+    pub fn volumes_with_balance(&self, balance: f32) -> Vec<i32> {
+        let balance = balance.clamp(-1.0, 1.0);
+        let max = self.volume as f32;
+        let left = if balance > 0.0 {
+            max * (1.0 - balance)
+        } else {
+            max
+        };
+        let right = if balance < 0.0 {
+            max * (1.0 + balance)
+        } else {
+            max
+        };
+        self.channels
+            .iter()
+            .map(|c| {
+                if c.is_left() {
+                    left.round() as i32
+                } else if c.is_right() {
+                    right.round() as i32
+                } else {
+                    self.volume
+                }
+            })
+            .collect()
+    }
+
+    /// Channel volumes with the same balance but the given loudest volume.
+    pub fn volumes_scaled_to(&self, volume: i32) -> Vec<i32> {
+        self.channels
+            .iter()
+            .map(|c| match self.volume {
+                0 => volume,
+                max => c.volume * volume / max,
+            })
+            .collect()
+    }
+
+    /// Target volume in percent after the given number of steps in the given mode.
+    pub fn volume_after_steps(&self, mode: StepMode, step: f32, steps: i32) -> f32 {
+        const MIN_DB: f32 = -60.0;
+        let change = step * steps as f32;
+        let volume = self.volume as f32;
+        match mode {
+            StepMode::Linear => volume + change,
+            StepMode::Db => {
+                let db = percent_to_db(volume).unwrap_or(MIN_DB).max(MIN_DB) + change;
+                if db <= MIN_DB {
+                    0.0
+                } else {
+                    db_to_percent(db)
+                }
+            }
+            StepMode::Cubic => {
+                let position = (volume / 100.0).cbrt() + change / 100.0;
+                100.0 * position.max(0.0).powi(3)
             }
         }
+    }
+
+    pub fn is_balanced(&self) -> bool {
+        self.balance.abs() < 0.01
+    }
+
+    /// E.g. "L 20%" if the right side is 20% quieter than the left one.
+    pub fn balance_string(&self) -> String {
+        let side = if self.balance < 0.0 { "L" } else { "R" };
+        format!("{} {:.0}%", side, self.balance.abs() * 100.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AMIXER_STEREO: &str = "Simple mixer control 'Master',0
+  Capabilities: pvolume pswitch pswitch-joined
+  Playback channels: Front Left - Front Right
+  Limits: Playback 0 - 65536
+  Mono:
+  Front Left: Playback 39322 [60%] [on]
+  Front Right: Playback 26214 [40%] [on]
+";
+
+    #[test]
+    fn parses_channels() {
+        let volume = VolumeInfo::parse(AMIXER_STEREO).unwrap();
+        assert_eq!(volume.volume, 60);
+        assert!(!volume.muted);
+        assert_eq!(volume.channels.len(), 2);
+        assert_eq!(volume.channels[0].name, "Front Left");
+        assert_eq!(volume.channels[1].volume, 40);
+        assert!(volume.balance < 0.0);
+    }
+
+    #[test]
+    fn rejects_output_without_channels() {
+        assert!(VolumeInfo::parse("amixer: Unable to find simple control 'Master',0\n").is_err());
+    }
+
+    #[test]
+    fn parses_decibels() {
+        let output = "Simple mixer control 'Master',0
+  Capabilities: pvolume pvolume-joined pswitch pswitch-joined
+  Playback channels: Mono
+  Limits: Playback 0 - 87
+  Mono: Playback 70 [80%] [-12.75dB] [off]
+";
+        let volume = VolumeInfo::parse(output).unwrap();
+        assert_eq!(volume.volume, 80);
+        assert!(volume.muted);
+        assert_eq!(volume.db, Some(-12.75));
+        assert_eq!(volume.balance, 0.0);
+        // Without dB from the mixer, the cubic scale of the server.
+        assert_eq!(
+            VolumeInfo::parse(AMIXER_STEREO).unwrap().db,
+            percent_to_db(60.0)
+        );
+    }
+
+    #[test]
+    fn converts_between_percent_and_db() {
+        assert_eq!(percent_to_db(0.0), None);
+        assert_eq!(percent_to_db(100.0), Some(0.0));
+        assert!((db_to_percent(percent_to_db(42.0).unwrap()) - 42.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn scales_channels_to_a_volume() {
+        let volume = VolumeInfo::parse(AMIXER_STEREO).unwrap();
+        assert_eq!(volume.volumes_scaled_to(30), vec![30, 20]);
+        assert_eq!(volume.volumes_scaled_to(0), vec![0, 0]);
+    }
+
+    #[test]
+    fn caps_volume_changes() {
+        assert_eq!(cap_volume_change(30, 10, Some(50)), 10);
+        assert_eq!(cap_volume_change(45, 10, Some(50)), 5);
+        assert_eq!(cap_volume_change(60, 10, Some(50)), 0);
+        assert_eq!(cap_volume_change(60, -10, Some(50)), -10);
+        assert_eq!(cap_volume_change(95, 10, None), 10);
+    }
 
-        Err(String::from("Could not parse volume.").into())
+    #[test]
+    fn caps_channel_volumes_keeping_the_balance() {
+        assert_eq!(cap_channel_volumes(&[80, 40], Some(50)), vec![50, 25]);
+        assert_eq!(cap_channel_volumes(&[40, 20], Some(50)), vec![40, 20]);
+        assert_eq!(cap_channel_volumes(&[120, 100], None), vec![120, 100]);
+        assert_eq!(cap_volume(150, Some(40)), 40);
+        assert_eq!(cap_volume(30, Some(40)), 30);
     }
 }
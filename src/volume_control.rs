@@ -1,39 +1,303 @@
 use regex::Regex;
+use std::io;
+use std::io::prelude::*;
 use std::process;
-use std::str;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
 
-use failure::{bail, Error};
-use log::{debug, error};
+use log::{debug, error, info};
 
-#[derive(Clone, Debug)]
-pub struct Interface {
+use crate::error::Error;
+
+/// An output of the sound server, e.g. speakers, HDMI or a bluetooth headset.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct Sink {
+    pub index: i32,
+    /// Whether this is the default sink.
+    pub active: bool,
+    /// "RUNNING", "IDLE" or "SUSPENDED".
+    pub state: String,
+    /// Description for display.
+    pub name: String,
+    /// Name the sound server knows the sink by.
+    pub sink_name: String,
+    /// Module that created the sink, e.g. "module-alsa-card.c" or "module-combine-sink.c".
+    pub driver: String,
+    /// Volume of each channel in percent.
+    pub volumes: Vec<i32>,
+    pub muted: bool,
+    /// Name of the active port.
+    pub port: Option<String>,
+    pub ports: Vec<Port>,
+}
+
+impl Sink {
+    /// Whether the sink plays on other sinks, see [`VolumeControl::create_combined_sink`].
+    pub fn is_combined(&self) -> bool {
+        self.driver.starts_with(COMBINE_MODULE)
+    }
+
+    /// Whether the sink plays on no device at all.
+    pub fn is_null(&self) -> bool {
+        self.driver.starts_with(NULL_MODULE)
+    }
+}
+
+/// A connector of a sink, e.g. speakers or headphones.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct Port {
+    pub name: String,
+    pub description: String,
+    /// False if nothing is plugged in.
+    pub available: bool,
+}
+
+/// A configuration of a sound card, e.g. "Analog Stereo Duplex".
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct Profile {
+    pub name: String,
+    pub description: String,
+    pub available: bool,
+}
+
+/// An input of the sound server, e.g. a microphone or the monitor of a sink.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct Source {
     pub index: i32,
+    /// Whether this is the default source.
     pub active: bool,
     pub state: String,
+    /// Description for display.
+    pub name: String,
+    /// Name the sound server knows the source by.
+    pub source_name: String,
+    /// Volume of each channel in percent.
+    pub volumes: Vec<i32>,
+    pub muted: bool,
+    /// Index of the sink this source monitors, if it is a monitor.
+    pub monitor_of: Option<i32>,
+}
+
+/// A sound card with its profile, e.g. "output:analog-stereo+input:analog-stereo".
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Card {
     pub name: String,
+    /// Description for display.
+    pub description: String,
+    /// Name of the active profile.
+    pub profile: Option<String>,
+    pub profiles: Vec<Profile>,
+}
+
+/// A stream playing on a sink.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct SinkInput {
+    pub index: i32,
+    /// Index of the sink the stream plays on.
+    pub sink: i32,
+    /// Whether the stream is paused.
+    pub corked: bool,
+    pub application: String,
+    pub process_id: Option<u32>,
+    /// E.g. "phone", "music" or "event".
+    pub role: String,
+    /// Volume of each channel in percent.
+    pub volumes: Vec<i32>,
+    pub muted: bool,
+}
+
+/// What happened in an [`Event`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum EventKind {
+    New,
+    Change,
+    Remove,
+}
+
+/// What an [`Event`] happened to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Facility {
+    Sink,
+    Source,
+    SinkInput,
+    SourceOutput,
+    Module,
+    Client,
+    SampleCache,
+    Server,
+    Card,
+}
+
+/// A change on the sound server, as reported by `pactl subscribe`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct Event {
+    pub kind: EventKind,
+    pub facility: Facility,
+    /// Index of the sink, stream and so on. The server has none.
+    pub index: Option<u32>,
+}
+
+impl Event {
+    /// Parses a line like "Event 'change' on sink-input #12".
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = line.trim().strip_prefix("Event '")?.split_whitespace();
+        let kind = match words.next()?.trim_end_matches('\'') {
+            "new" => EventKind::New,
+            "change" => EventKind::Change,
+            "remove" => EventKind::Remove,
+            _ => return None,
+        };
+        if words.next()? != "on" {
+            return None;
+        }
+        let facility = match words.next()? {
+            "sink" => Facility::Sink,
+            "source" => Facility::Source,
+            "sink-input" => Facility::SinkInput,
+            "source-output" => Facility::SourceOutput,
+            "module" => Facility::Module,
+            "client" => Facility::Client,
+            "sample-cache" => Facility::SampleCache,
+            "server" => Facility::Server,
+            "card" => Facility::Card,
+            _ => return None,
+        };
+        let index = words
+            .next()
+            .and_then(|i| i.trim_start_matches('#').parse().ok());
+        Some(Event {
+            kind,
+            facility,
+            index,
+        })
+    }
+}
+
+/// The events of `pactl subscribe`, until the sound server stops. Stops `pactl subscribe` when
+/// dropped.
+pub struct Events {
+    child: process::Child,
+    lines: io::Lines<io::BufReader<process::ChildStdout>>,
+}
+
+/// Follows the changes on the sound server.
+pub fn subscribe() -> Result<Events, Error> {
+    let mut child = process::Command::new("pactl")
+        .arg("subscribe")
+        .stdout(process::Stdio::piped())
+        .spawn()
+        .map_err(|e| spawn_error("pactl", e))?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| Error::Unavailable(String::from("pactl subscribe has no output.")))?;
+    Ok(Events {
+        child,
+        lines: io::BufReader::new(stdout).lines(),
+    })
+}
+
+impl Iterator for Events {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        for line in self.lines.by_ref().map_while(Result::ok) {
+            match Event::parse(&line) {
+                Some(event) => return Some(event),
+                None => debug!("Ignoring \"{}\" from pactl subscribe.", line),
+            }
+        }
+        None
+    }
+}
+
+impl Drop for Events {
+    fn drop(&mut self) {
+        self.child.kill().unwrap_or(());
+        self.child.wait().map(|_| ()).unwrap_or(());
+    }
+}
+
+/// Sends on the channel whenever something changed on the given facilities, until the receiver
+/// is gone. Restarts `pactl subscribe` if it ends, e.g. when the sound server restarts.
+pub(crate) fn follow_changes(facilities: &'static [Facility]) -> Receiver<()> {
+    const RESTART_DELAY: Duration = Duration::from_secs(5);
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || loop {
+        match subscribe() {
+            Ok(events) => {
+                for event in events {
+                    if facilities.contains(&event.facility) && sender.send(()).is_err() {
+                        return;
+                    }
+                }
+                error!("pactl subscribe ended, restarting it.");
+            }
+            Err(e) => error!("Failed to run pactl subscribe: {}", e),
+        }
+        thread::sleep(RESTART_DELAY);
+    });
+    receiver
 }
 
+/// Plays on several sinks at once.
+const COMBINE_MODULE: &str = "module-combine-sink";
+/// Plays on nothing, e.g. to record from its monitor.
+const NULL_MODULE: &str = "module-null-sink";
+
 pub struct VolumeControl {
-    interfaces: Vec<Interface>,
-    pub active_interface: Option<Interface>,
+    interfaces: Vec<Sink>,
+    pub active_interface: Option<Sink>,
+}
+
+fn get_current_audio_outputs() -> Result<Vec<Sink>, Error> {
+    parse_sinks(&run_backend("pacmd", &["list-sinks"])?)
 }
 
-fn get_current_audio_outputs() -> Result<Vec<Interface>, Error> {
-    let sinks_list = process::Command::new("pacmd")
-        .args(&["list-sinks"])
-        .output()?;
-    let sinks_output = str::from_utf8(&sinks_list.stdout)?;
+/// A port or profile, e.g. "analog-output-speaker: Speakers (priority 10000, available: no)".
+const OPTION_PATTERN: &str = r"^\s*(?P<n>\S+): (?P<d>.*) \(priority (?P<rest>.*)\)\s*$";
+
+/// Name, description and availability of a port or profile.
+fn parse_option(option_re: &Regex, line: &str) -> Option<(String, String, bool)> {
+    let captures = option_re.captures(line)?;
+    Some((
+        captures["n"].to_string(),
+        captures["d"].to_string(),
+        !captures["rest"].contains("available: no"),
+    ))
+}
 
+/// Parses the output of `pacmd list-sinks`.
+pub(crate) fn parse_sinks(sinks_output: &str) -> Result<Vec<Sink>, Error> {
     let mut all_interfaces = Vec::new();
-    let mut next_interface: Option<Interface> = None;
+    let mut next_interface: Option<Sink> = None;
 
-    let add_to_list = |all_interfaces: &mut Vec<Interface>, next_interface: Option<Interface>| {
+    let add_to_list = |all_interfaces: &mut Vec<Sink>, next_interface: Option<Sink>| {
         if let Some(interface) = next_interface {
             debug!("  - Found sink: {:?}", interface);
             all_interfaces.push(interface);
         }
     };
 
+    let index_re = Regex::new(r"^.*index: (?P<i>[0-9]*).*$")?;
+    let description_re = Regex::new(r#"^.*device.description = "(?P<n>.*)".*$"#)?;
+    let name_re = Regex::new(r"^.*name: <(?P<n>.*)>.*$")?;
+    let driver_re = Regex::new(r"^.*driver: <(?P<d>.*)>.*$")?;
+    let volume_re = Regex::new(r"(?P<v>[0-9]+)%")?;
+    let port_re = Regex::new(r"^.*active port: <(?P<p>.*)>.*$")?;
+    let option_re = Regex::new(OPTION_PATTERN)?;
+    let mut in_ports = false;
+
     debug!("Collecting current audio sinks:");
     for line in sinks_output.lines() {
         if line.contains("index") {
@@ -42,14 +306,23 @@ fn get_current_audio_outputs() -> Result<Vec<Interface>, Error> {
             add_to_list(&mut all_interfaces, next_interface);
 
             let active = line.trim().starts_with("*");
-            let re = Regex::new(r"^.*index: (?P<i>[0-9]*).*$")?;
-            let index: i32 = re.replace(line, "$i").parse()?;
-            next_interface = Some(Interface {
-                index: index,
-                active: active,
+            let index: i32 = index_re
+                .replace(line, "$i")
+                .parse()
+                .map_err(|e| Error::parse(line, e))?;
+            next_interface = Some(Sink {
+                index,
+                active,
                 state: String::new(),
                 name: String::new(),
+                sink_name: String::new(),
+                driver: String::new(),
+                volumes: Vec::new(),
+                muted: false,
+                port: None,
+                ports: Vec::new(),
             });
+            in_ports = false;
         } else if line.contains("state:") {
             if let Some(interface) = &mut next_interface {
                 if let Some(state) = line.rsplit(' ').next() {
@@ -58,8 +331,43 @@ fn get_current_audio_outputs() -> Result<Vec<Interface>, Error> {
             }
         } else if line.contains("device.description") {
             if let Some(interface) = &mut next_interface {
-                let re = Regex::new(r#"^.*device.description = "(?P<n>.*)".*$"#)?;
-                interface.name = re.replace_all(line, "$n").to_string();
+                interface.name = description_re.replace_all(line, "$n").to_string();
+            }
+        } else if line.trim().starts_with("name:") {
+            if let Some(interface) = &mut next_interface {
+                interface.sink_name = name_re.replace(line, "$n").to_string();
+            }
+        } else if line.trim().starts_with("driver:") {
+            if let Some(interface) = &mut next_interface {
+                interface.driver = driver_re.replace(line, "$d").to_string();
+            }
+        } else if line.trim().starts_with("volume:") {
+            if let Some(interface) = &mut next_interface {
+                interface.volumes = volume_re
+                    .captures_iter(line)
+                    .filter_map(|c| c["v"].parse().ok())
+                    .collect();
+            }
+        } else if line.trim().starts_with("muted:") {
+            if let Some(interface) = &mut next_interface {
+                interface.muted = line.trim().ends_with("yes");
+            }
+        } else if line.trim().starts_with("active port:") {
+            in_ports = false;
+            if let Some(interface) = &mut next_interface {
+                interface.port = Some(port_re.replace(line, "$p").to_string());
+            }
+        } else if line.trim() == "ports:" {
+            in_ports = true;
+        } else if in_ports {
+            if let (Some(interface), Some((name, description, available))) =
+                (&mut next_interface, parse_option(&option_re, line))
+            {
+                interface.ports.push(Port {
+                    name,
+                    description,
+                    available,
+                });
             }
         }
     }
@@ -69,7 +377,7 @@ fn get_current_audio_outputs() -> Result<Vec<Interface>, Error> {
     Ok(all_interfaces)
 }
 
-pub fn get_active_interface(interfaces: &Vec<Interface>) -> Option<Interface> {
+pub fn get_active_interface(interfaces: &Vec<Sink>) -> Option<Sink> {
     for interface in interfaces {
         if interface.active {
             debug!("Found active interface: {:?}", interface);
@@ -84,50 +392,153 @@ impl VolumeControl {
         let interfaces = get_current_audio_outputs()?;
         let active_interface = get_active_interface(&interfaces);
         Ok(Self {
-            interfaces: interfaces,
-            active_interface: active_interface,
+            interfaces,
+            active_interface,
         })
     }
 
     pub fn change_volume(&self, amount: i32) -> Result<(), Error> {
         let amount_absolute = amount.abs();
         let direction_sign = if amount >= 0 { '+' } else { '-' };
-        let output = process::Command::new("amixer")
-            .args(&[
+        run_backend(
+            "amixer",
+            &[
                 "-D",
                 "pulse",
                 "sset",
                 "Master",
                 &format!("{}%{}", amount_absolute, direction_sign),
-            ])
-            .output()?;
-        if !output.status.success() {
-            error!(
-                "Failed to change volume: {}",
-                str::from_utf8(&output.stderr).unwrap()
-            );
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Sets the volume of each channel of the active interface, in percent.
+    pub fn set_channel_volumes(&self, volumes: &[i32]) -> Result<(), Error> {
+        let active_interface = self.active_interface.as_ref().ok_or(Error::NoActiveSink)?;
+        self.set_sink_volumes(&active_interface.index.to_string(), volumes)
+    }
+
+    /// Sets the volume of each channel of a sink, given by index or name, in percent.
+    pub fn set_sink_volumes(&self, sink: &str, volumes: &[i32]) -> Result<(), Error> {
+        let mut args = vec![String::from("set-sink-volume"), sink.to_string()];
+        args.extend(volumes.iter().map(|v| format!("{}%", v)));
+        run_pactl(&args)
+    }
+
+    pub fn set_sink_mute(&self, sink: &str, muted: bool) -> Result<(), Error> {
+        run_pactl(&["set-sink-mute", sink, if muted { "1" } else { "0" }])
+    }
+
+    pub fn set_sink_port(&self, sink: &str, port: &str) -> Result<(), Error> {
+        run_pactl(&["set-sink-port", sink, port])
+    }
+
+    pub fn set_card_profile(&self, card: &str, profile: &str) -> Result<(), Error> {
+        run_pactl(&["set-card-profile", card, profile])
+    }
+
+    /// Creates a sink that plays on all the given sinks at once, replacing any combined sink of
+    /// the same name. pipewire-pulse provides the same module.
+    pub fn create_combined_sink(&mut self, name: &str, sinks: &[Sink]) -> Result<(), Error> {
+        self.remove_combined_sink(name)?;
+        let slaves: Vec<&str> = sinks.iter().map(|s| s.sink_name.as_str()).collect();
+        // Quotes would end the quoted properties early.
+        let description: Vec<String> = sinks
+            .iter()
+            .map(|s| s.name.replace(['\'', '"'], ""))
+            .collect();
+        info!("Combining {} into {}.", slaves.join(", "), name);
+        run_pactl(&[
+            "load-module",
+            COMBINE_MODULE,
+            &format!("sink_name={}", name),
+            &format!("slaves={}", slaves.join(",")),
+            &format!(
+                "sink_properties=\"device.description='{}'\"",
+                description.join(" + ")
+            ),
+        ])
+    }
+
+    /// Unloads the combined sink with the given name. Returns whether there was one.
+    pub fn remove_combined_sink(&mut self, name: &str) -> Result<bool, Error> {
+        let sink_name = format!("sink_name={}", name);
+        let mut removed = false;
+        // "<index>\t<module>\t<arguments>"
+        for line in run_backend("pactl", &["list", "short", "modules"])?.lines() {
+            let mut fields = line.split('\t');
+            if let (Some(index), Some(COMBINE_MODULE), Some(arguments)) =
+                (fields.next(), fields.next(), fields.next())
+            {
+                if arguments.split_whitespace().any(|a| a == sink_name) {
+                    info!("Removing the combined sink {} (module {}).", name, index);
+                    run_pactl(&["unload-module", index])?;
+                    removed = true;
+                }
+            }
         }
+        Ok(removed)
+    }
 
+    pub fn set_default_sink(&mut self, sink: &str) -> Result<(), Error> {
+        run_pactl(&["set-default-sink", sink])?;
+        self.get_available_interfaces()?;
         Ok(())
     }
 
+    /// Sets the volume of each channel of a source, given by index or name, in percent.
+    pub fn set_source_volumes(&self, source: &str, volumes: &[i32]) -> Result<(), Error> {
+        let mut args = vec![String::from("set-source-volume"), source.to_string()];
+        args.extend(volumes.iter().map(|v| format!("{}%", v)));
+        run_pactl(&args)
+    }
+
+    pub fn set_default_source(&self, source: &str) -> Result<(), Error> {
+        run_pactl(&["set-default-source", source])
+    }
+
+    pub fn set_source_mute(&self, source: &str, muted: bool) -> Result<(), Error> {
+        run_pactl(&["set-source-mute", source, if muted { "1" } else { "0" }])
+    }
+
+    pub fn toggle_source_mute(&self, source: &str) -> Result<(), Error> {
+        run_pactl(&["set-source-mute", source, "toggle"])
+    }
+
+    pub fn set_sink_input_volumes(&self, sink_input: i32, volumes: &[i32]) -> Result<(), Error> {
+        let mut args = vec![
+            String::from("set-sink-input-volume"),
+            sink_input.to_string(),
+        ];
+        args.extend(volumes.iter().map(|v| format!("{}%", v)));
+        run_pactl(&args)
+    }
+
+    pub fn set_sink_input_mute(&self, sink_input: i32, muted: bool) -> Result<(), Error> {
+        run_pactl(&[
+            "set-sink-input-mute",
+            &sink_input.to_string(),
+            if muted { "1" } else { "0" },
+        ])
+    }
+
+    pub fn move_sink_input(&self, sink_input: i32, sink: &str) -> Result<(), Error> {
+        run_pactl(&["move-sink-input", &sink_input.to_string(), sink])
+    }
+
     pub fn toggle_mute(&mut self) -> Result<(), Error> {
         self.interfaces = get_current_audio_outputs()?;
 
-        if let Some(active_interface) = &self.active_interface {
-            process::Command::new("pactl")
-                .args(&[
-                    "set-sink-mute",
-                    &active_interface.index.to_string(),
-                    "toggle",
-                ])
-                .output()?;
-        }
-
-        Ok(())
+        let active_interface = self.active_interface.as_ref().ok_or(Error::NoActiveSink)?;
+        run_pactl(&[
+            "set-sink-mute",
+            &active_interface.index.to_string(),
+            "toggle",
+        ])
     }
 
-    pub fn get_available_interfaces(&mut self) -> Result<&Vec<Interface>, Error> {
+    pub fn get_available_interfaces(&mut self) -> Result<&Vec<Sink>, Error> {
         self.interfaces = get_current_audio_outputs()?;
         self.active_interface = get_active_interface(&self.interfaces);
         Ok(&self.interfaces)
@@ -137,13 +548,12 @@ impl VolumeControl {
         self.interfaces = get_current_audio_outputs()?;
 
         if self.interfaces.len() <= 1 {
-            bail!("Not enough active interfaces.");
+            return Err(Error::Unavailable(String::from(
+                "Not enough active interfaces.",
+            )));
         }
 
-        let current_index = match self.interfaces.iter().position(|i| i.active) {
-            Some(index) => index,
-            None => 0,
-        };
+        let current_index = self.interfaces.iter().position(|i| i.active).unwrap_or(0);
         let next_interface_index = (current_index + 1) % self.interfaces.len();
         debug!(
             "Switching to the next interface: {} -> {}",
@@ -151,39 +561,517 @@ impl VolumeControl {
         );
 
         let next_interface_index = self.interfaces[next_interface_index].index;
-        process::Command::new("pactl")
-            .args(&["set-default-sink", &next_interface_index.to_string()])
-            .output()?;
+        self.select_interface(next_interface_index)
+    }
+
+    /// Makes the sink with the given index the default one and moves all streams to it.
+    pub fn select_interface(&mut self, next_interface_index: i32) -> Result<(), Error> {
+        run_pactl(&["set-default-sink", &next_interface_index.to_string()])?;
 
         let sink_inputs = list_sink_inputs()?;
-        for sink in sink_inputs.iter() {
-            debug!("Moving sink input {:?} to new output.", sink);
-            process::Command::new("pacmd")
-                .args(&[
+        for sink_input in sink_inputs.iter() {
+            debug!("Moving sink input {:?} to new output.", sink_input);
+            run_backend(
+                "pacmd",
+                &[
                     "move-sink-input",
-                    &sink.to_string(),
+                    &sink_input.index.to_string(),
                     &next_interface_index.to_string(),
-                ])
-                .output()?;
+                ],
+            )?;
         }
 
         Ok(())
     }
 }
 
-fn list_sink_inputs() -> Result<Vec<i32>, Error> {
-    let output = process::Command::new("pacmd")
-        .args(&["list-sink-inputs"])
-        .output()?;
-    let output = str::from_utf8(&output.stdout)?;
-    let mut result = Vec::new();
+/// Runs a program of the sound server and returns its output.
+pub(crate) fn run_backend<S: AsRef<str>>(program: &str, args: &[S]) -> Result<String, Error> {
+    let args: Vec<&str> = args.iter().map(|a| a.as_ref()).collect();
+    check_output(
+        program,
+        &args,
+        process::Command::new(program).args(&args).output(),
+    )
+}
+
+/// Error for a program of the sound server that could not be started.
+pub(crate) fn spawn_error(program: &str, e: io::Error) -> Error {
+    if e.kind() == io::ErrorKind::NotFound {
+        Error::BackendNotFound(format!("{} is not installed", program))
+    } else {
+        e.into()
+    }
+}
+
+/// Turns the result of running a program of the sound server into its output or an error.
+pub(crate) fn check_output(
+    program: &str,
+    args: &[&str],
+    output: io::Result<process::Output>,
+) -> Result<String, Error> {
+    let output = output.map_err(|e| spawn_error(program, e))?;
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    if !output.status.success() {
+        // pactl and pacmd fail like this if there is no server to connect to.
+        if stderr.contains("Connection refused") || stderr.contains("No PulseAudio daemon") {
+            return Err(Error::BackendNotFound(stderr));
+        }
+        return Err(Error::CommandFailed {
+            command: format!("{} {}", program, args.join(" ")),
+            stderr,
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn run_pactl<S: AsRef<str>>(args: &[S]) -> Result<(), Error> {
+    run_backend("pactl", args)?;
+    Ok(())
+}
+
+pub fn get_default_source() -> Result<String, Error> {
+    let output = run_backend("pactl", &["info"])?;
+    match output
+        .lines()
+        .find_map(|l| l.strip_prefix("Default Source: "))
+    {
+        Some(source) => Ok(source.trim().to_string()),
+        None => Err(Error::Unavailable(String::from("No default source."))),
+    }
+}
+
+pub fn is_source_muted(source: &str) -> Result<bool, Error> {
+    let output = run_backend("pacmd", &["dump"])?;
+    let prefix = format!("set-source-mute {} ", source);
+    match output.lines().find_map(|l| l.strip_prefix(&prefix)) {
+        Some(muted) => Ok(muted.trim() == "yes"),
+        None => Err(Error::Unavailable(format!("Unknown source {}.", source))),
+    }
+}
+
+pub fn list_sink_inputs() -> Result<Vec<SinkInput>, Error> {
+    parse_sink_inputs(&run_backend("pacmd", &["list-sink-inputs"])?)
+}
+
+/// Parses the output of `pacmd list-sink-inputs`.
+pub(crate) fn parse_sink_inputs(output: &str) -> Result<Vec<SinkInput>, Error> {
+    let mut result: Vec<SinkInput> = Vec::new();
     let re = Regex::new(r"^.*index: (?P<i>[0-9]+).*$")?;
+    let sink_re = Regex::new(r"^.*sink: (?P<i>[0-9]+).*$")?;
+    let application_re = Regex::new(r#"^.*application.name = "(?P<n>.*)".*$"#)?;
+    let process_id_re = Regex::new(r#"^.*application.process.id = "(?P<i>[0-9]+)".*$"#)?;
+    let role_re = Regex::new(r#"^.*media.role = "(?P<r>.*)".*$"#)?;
+    let volume_re = Regex::new(r"(?P<v>[0-9]+)%")?;
     for line in output.lines() {
         if line.contains("index") {
-            let index: i32 = re.replace(line, "$i").parse()?;
-            result.push(index);
+            let index: i32 = re
+                .replace(line, "$i")
+                .parse()
+                .map_err(|e| Error::parse(line, e))?;
+            result.push(SinkInput {
+                index,
+                sink: -1,
+                corked: false,
+                application: String::new(),
+                process_id: None,
+                role: String::new(),
+                volumes: Vec::new(),
+                muted: false,
+            });
+        } else if line.trim().starts_with("state:") {
+            if let Some(sink_input) = result.last_mut() {
+                sink_input.corked = line.trim().ends_with("CORKED");
+            }
+        } else if line.trim().starts_with("sink:") {
+            if let Some(sink_input) = result.last_mut() {
+                sink_input.sink = sink_re
+                    .replace(line, "$i")
+                    .parse()
+                    .map_err(|e| Error::parse(line, e))?;
+            }
+        } else if line.trim().starts_with("application.name =") {
+            if let Some(sink_input) = result.last_mut() {
+                sink_input.application = application_re.replace(line, "$n").to_string();
+            }
+        } else if line.trim().starts_with("application.process.id =") {
+            if let Some(sink_input) = result.last_mut() {
+                sink_input.process_id = process_id_re.replace(line, "$i").parse().ok();
+            }
+        } else if line.trim().starts_with("media.role =") {
+            if let Some(sink_input) = result.last_mut() {
+                sink_input.role = role_re.replace(line, "$r").to_string();
+            }
+        } else if line.trim().starts_with("volume:") {
+            if let Some(sink_input) = result.last_mut() {
+                sink_input.volumes = volume_re
+                    .captures_iter(line)
+                    .filter_map(|c| c["v"].parse().ok())
+                    .collect();
+            }
+        } else if line.trim().starts_with("muted:") {
+            if let Some(sink_input) = result.last_mut() {
+                sink_input.muted = line.trim().ends_with("yes");
+            }
         }
     }
 
     Ok(result)
 }
+
+pub fn list_sources() -> Result<Vec<Source>, Error> {
+    parse_sources(&run_backend("pacmd", &["list-sources"])?)
+}
+
+/// Parses the output of `pacmd list-sources`.
+pub(crate) fn parse_sources(output: &str) -> Result<Vec<Source>, Error> {
+    let mut result: Vec<Source> = Vec::new();
+    let index_re = Regex::new(r"^.*index: (?P<i>[0-9]+).*$")?;
+    let description_re = Regex::new(r#"^.*device.description = "(?P<n>.*)".*$"#)?;
+    let name_re = Regex::new(r"^.*name: <(?P<n>.*)>.*$")?;
+    let volume_re = Regex::new(r"(?P<v>[0-9]+)%")?;
+    let monitor_re = Regex::new(r"^.*monitor_of: (?P<i>[0-9]+).*$")?;
+    for line in output.lines() {
+        let trimmed = line.trim();
+        if line.contains("index:") {
+            result.push(Source {
+                index: index_re
+                    .replace(line, "$i")
+                    .parse()
+                    .map_err(|e| Error::parse(line, e))?,
+                active: trimmed.starts_with('*'),
+                state: String::new(),
+                name: String::new(),
+                source_name: String::new(),
+                volumes: Vec::new(),
+                muted: false,
+                monitor_of: None,
+            });
+        } else if let Some(source) = result.last_mut() {
+            if trimmed.starts_with("state:") {
+                source.state = trimmed.trim_start_matches("state:").trim().to_string();
+            } else if trimmed.starts_with("name:") {
+                source.source_name = name_re.replace(line, "$n").to_string();
+            } else if trimmed.starts_with("device.description =") {
+                source.name = description_re.replace(line, "$n").to_string();
+            } else if trimmed.starts_with("volume:") {
+                source.volumes = volume_re
+                    .captures_iter(line)
+                    .filter_map(|c| c["v"].parse().ok())
+                    .collect();
+            } else if trimmed.starts_with("muted:") {
+                source.muted = trimmed.ends_with("yes");
+            } else if trimmed.starts_with("monitor_of:") {
+                source.monitor_of = monitor_re.replace(line, "$i").parse().ok();
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+pub fn list_cards() -> Result<Vec<Card>, Error> {
+    let output = run_backend("pacmd", &["list-cards"])?;
+    let mut result: Vec<Card> = Vec::new();
+    let name_re = Regex::new(r"^.*name: <(?P<n>.*)>.*$")?;
+    let description_re = Regex::new(r#"^.*device.description = "(?P<n>.*)".*$"#)?;
+    let profile_re = Regex::new(r"^.*active profile: <(?P<p>.*)>.*$")?;
+    let option_re = Regex::new(OPTION_PATTERN)?;
+    // Cards list their ports as well, in the same format as the profiles.
+    let mut in_profiles = false;
+    for line in output.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("name:") {
+            result.push(Card {
+                name: name_re.replace(line, "$n").to_string(),
+                description: String::new(),
+                profile: None,
+                profiles: Vec::new(),
+            });
+        } else if let Some(card) = result.last_mut() {
+            if trimmed == "profiles:" {
+                in_profiles = true;
+            } else if trimmed.starts_with("active profile:") {
+                in_profiles = false;
+                card.profile = Some(profile_re.replace(line, "$p").to_string());
+            } else if trimmed.starts_with("device.description =") && card.description.is_empty() {
+                card.description = description_re.replace(line, "$n").to_string();
+            } else if in_profiles {
+                if let Some((name, description, available)) = parse_option(&option_re, line) {
+                    card.profiles.push(Profile {
+                        name,
+                        description,
+                        available,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_events() {
+        assert_eq!(
+            Event::parse("Event 'change' on sink-input #12"),
+            Some(Event {
+                kind: EventKind::Change,
+                facility: Facility::SinkInput,
+                index: Some(12),
+            })
+        );
+        assert_eq!(
+            Event::parse("Event 'new' on card #0\n"),
+            Some(Event {
+                kind: EventKind::New,
+                facility: Facility::Card,
+                index: Some(0),
+            })
+        );
+        assert_eq!(
+            Event::parse("Event 'remove' on server").map(|e| (e.kind, e.facility, e.index)),
+            Some((EventKind::Remove, Facility::Server, None))
+        );
+        assert_eq!(Event::parse("Event 'unknown' on sink #1"), None);
+        assert_eq!(Event::parse("Event 'change' at sink #1"), None);
+        assert_eq!(Event::parse("Connection failure: Connection refused"), None);
+        assert_eq!(Event::parse(""), None);
+    }
+
+    #[test]
+    fn parses_sink_inputs() {
+        let output = "2 sink input(s) available.
+    index: 41
+	driver: <protocol-native.c>
+	flags: START_CORKED 
+	state: RUNNING
+	sink: 0 <alsa_output.pci-0000_00_1f.3.analog-stereo>
+	volume: front-left: 65536 / 100% / 0.00 dB,   front-right: 52429 /  80% / -5.81 dB
+	        balance -0.20
+	muted: no
+	current latency: 70.43 ms
+	properties:
+		media.name = \"Playback\"
+		application.name = \"Firefox\"
+		application.process.id = \"2145\"
+		application.process.binary = \"firefox\"
+    index: 42
+	driver: <protocol-native.c>
+	state: CORKED
+	sink: 3 <combined>
+	volume: mono: 32768 /  50% / -18.06 dB
+	muted: yes
+	properties:
+		application.name = \"ZOOM VoiceEngine\"
+		media.role = \"phone\"
+";
+        let sink_inputs = parse_sink_inputs(output).unwrap();
+        assert_eq!(
+            sink_inputs,
+            vec![
+                SinkInput {
+                    index: 41,
+                    sink: 0,
+                    corked: false,
+                    application: String::from("Firefox"),
+                    process_id: Some(2145),
+                    role: String::new(),
+                    volumes: vec![100, 80],
+                    muted: false,
+                },
+                SinkInput {
+                    index: 42,
+                    sink: 3,
+                    corked: true,
+                    application: String::from("ZOOM VoiceEngine"),
+                    process_id: None,
+                    role: String::from("phone"),
+                    volumes: vec![50],
+                    muted: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_sources() {
+        let output = "2 source(s) available.
+    index: 1
+	name: <alsa_output.pci-0000_00_1f.3.analog-stereo.monitor>
+	driver: <module-alsa-card.c>
+	state: RUNNING
+	volume: front-left: 65536 / 100% / 0.00 dB,   front-right: 65536 / 100% / 0.00 dB
+	muted: no
+	monitor_of: 0
+	properties:
+		device.description = \"Monitor of Built-in Audio Analog Stereo\"
+  * index: 2
+	name: <alsa_input.pci-0000_00_1f.3.analog-stereo>
+	driver: <module-alsa-card.c>
+	state: SUSPENDED
+	volume: front-left: 45875 /  70% / -9.29 dB,   front-right: 45875 /  70% / -9.29 dB
+	muted: yes
+	properties:
+		device.description = \"Built-in Audio Analog Stereo\"
+";
+        let sources = parse_sources(output).unwrap();
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[0].index, 1);
+        assert!(!sources[0].active);
+        assert_eq!(sources[0].monitor_of, Some(0));
+        assert_eq!(sources[0].name, "Monitor of Built-in Audio Analog Stereo");
+        assert_eq!(sources[1].index, 2);
+        assert!(sources[1].active);
+        assert_eq!(sources[1].state, "SUSPENDED");
+        assert_eq!(
+            sources[1].source_name,
+            "alsa_input.pci-0000_00_1f.3.analog-stereo"
+        );
+        assert_eq!(sources[1].volumes, vec![70, 70]);
+        assert!(sources[1].muted);
+        assert_eq!(sources[1].monitor_of, None);
+    }
+
+    #[test]
+    fn parses_sinks_with_ports() {
+        let output = "2 sink(s) available.
+  * index: 0
+	name: <alsa_output.pci-0000_00_1f.3.analog-stereo>
+	driver: <module-alsa-card.c>
+	flags: HARDWARE HW_MUTE_CTRL HW_VOLUME_CTRL DECIBEL_VOLUME LATENCY DYNAMIC_LATENCY
+	state: RUNNING
+	suspend cause: (none)
+	priority: 9039
+	volume: front-left: 39321 /  60% / -13.31 dB,   front-right: 32768 /  50% / -18.06 dB
+	        balance -0.17
+	base volume: 65536 / 100% / 0.00 dB
+	volume steps: 65537
+	muted: no
+	current latency: 24.53 ms
+	monitor source: 1
+	sample spec: s16le 2ch 48000Hz
+	channel map: front-left,front-right
+	module: 7
+	properties:
+		device.api = \"alsa\"
+		device.description = \"Built-in Audio Analog Stereo\"
+		device.icon_name = \"audio-card-pci\"
+	ports:
+		analog-output-speaker: Speakers (priority 10000, latency offset 0 usec, available: unknown)
+			properties:
+				device.icon_name = \"audio-speakers\"
+		analog-output-headphones: Headphones (priority 9900, latency offset 0 usec, available: no)
+			properties:
+				device.icon_name = \"audio-headphones\"
+	active port: <analog-output-speaker>
+    index: 2
+	name: <alsa_output.pci-0000_01_00.1.hdmi-stereo>
+	driver: <module-alsa-card.c>
+	state: SUSPENDED
+	volume: front-left: 65536 / 100% / 0.00 dB,   front-right: 65536 / 100% / 0.00 dB
+	muted: yes
+	properties:
+		device.description = \"HDMI\"
+";
+        let sinks = parse_sinks(output).unwrap();
+        assert_eq!(sinks.len(), 2);
+
+        let speakers = &sinks[0];
+        assert_eq!(speakers.index, 0);
+        assert!(speakers.active);
+        assert_eq!(speakers.state, "RUNNING");
+        assert_eq!(speakers.name, "Built-in Audio Analog Stereo");
+        assert_eq!(
+            speakers.sink_name,
+            "alsa_output.pci-0000_00_1f.3.analog-stereo"
+        );
+        assert_eq!(speakers.volumes, vec![60, 50]);
+        assert!(!speakers.muted);
+        assert_eq!(speakers.port.as_deref(), Some("analog-output-speaker"));
+        assert_eq!(
+            speakers.ports,
+            vec![
+                Port {
+                    name: String::from("analog-output-speaker"),
+                    description: String::from("Speakers"),
+                    available: true,
+                },
+                Port {
+                    name: String::from("analog-output-headphones"),
+                    description: String::from("Headphones"),
+                    available: false,
+                },
+            ]
+        );
+
+        let hdmi = &sinks[1];
+        assert_eq!(hdmi.index, 2);
+        assert!(!hdmi.active);
+        assert!(hdmi.muted);
+        assert_eq!(hdmi.port, None);
+        assert!(hdmi.ports.is_empty());
+        assert_eq!(get_active_interface(&sinks), Some(speakers.clone()));
+    }
+
+    #[test]
+    fn parses_no_sinks() {
+        assert!(parse_sinks("0 sink(s) available.\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn tells_combined_and_null_sinks_apart() {
+        let output = "3 sink(s) available.
+  * index: 0
+	name: <alsa_output.pci-0000_00_1f.3.analog-stereo>
+	driver: <module-alsa-card.c>
+	state: RUNNING
+	volume: front-left: 39321 /  60% / -13.31 dB,   front-right: 39321 /  60% / -13.31 dB
+	muted: no
+	properties:
+		device.description = \"Built-in Audio Analog Stereo\"
+    index: 3
+	name: <combined>
+	driver: <module-combine-sink.c>
+	flags: DECIBEL_VOLUME LATENCY DYNAMIC_LATENCY
+	state: SUSPENDED
+	volume: front-left: 65536 / 100% / 0.00 dB,   front-right: 65536 / 100% / 0.00 dB
+	muted: no
+	properties:
+		device.description = \"Speakers + HDMI\"
+		device.class = \"filter\"
+    index: 4
+	name: <recording>
+	driver: <module-null-sink.c>
+	state: IDLE
+	volume: front-left: 65536 / 100% / 0.00 dB,   front-right: 65536 / 100% / 0.00 dB
+	muted: no
+	properties:
+		device.description = \"Null Output\"
+";
+        let sinks = parse_sinks(output).unwrap();
+        assert_eq!(
+            sinks
+                .iter()
+                .map(|s| s.driver.as_str())
+                .collect::<Vec<&str>>(),
+            vec![
+                "module-alsa-card.c",
+                "module-combine-sink.c",
+                "module-null-sink.c"
+            ]
+        );
+        assert_eq!(
+            sinks
+                .iter()
+                .map(|s| (s.is_combined(), s.is_null()))
+                .collect::<Vec<(bool, bool)>>(),
+            vec![(false, false), (true, false), (false, true)]
+        );
+        assert_eq!(sinks[1].name, "Speakers + HDMI");
+    }
+}